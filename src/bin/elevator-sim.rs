@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use elevator::building_config::BuildingConfig;
+use elevator::logging;
+use elevator::simulation::{Simulation, traffic::TrafficConfig};
+
+/* `elevator-sim --config building.json --traffic traffic.json --dispatch nearest-car --json` */
+/* without a traffic file: `--duration <secs>`, `--rate <passengers per minute per floor>` and `--seed` */
+/* `--quiet` leaves only the simulator's own warnings and the report */
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let value_of = |flag: &str| -> Option<&String> {
+        args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1))
+    };
+
+    let building = BuildingConfig::from_args(&args).map_err(std::io::Error::other)?;
+
+    if args.iter().any(|a| a == "--quiet") {
+        logging::set_quiet(true);
+    }
+
+    let mut traffic = match value_of("--traffic") {
+        Some(path) => {
+            let content = std::fs::read_to_string(Path::new(path))?;
+            serde_json::from_str::<TrafficConfig>(&content).map_err(|e| std::io::Error::other(format!("invalid traffic {}: {}", path, e)))?
+        }
+        None => TrafficConfig::default(),
+    };
+
+    if let Some(duration) = value_of("--duration") {
+        traffic.duration_secs = duration.parse().map_err(|_| std::io::Error::other(format!("invalid --duration {}", duration)))?;
+    }
+
+    if let Some(rate) = value_of("--rate") {
+        traffic.arrivals_per_minute = vec![rate.parse().map_err(|_| std::io::Error::other(format!("invalid --rate {}", rate)))?];
+    }
+
+    if let Some(seed) = value_of("--seed") {
+        traffic.seed = seed.parse().map_err(|_| std::io::Error::other(format!("invalid --seed {}", seed)))?;
+    }
+
+    let dispatch_strategy = building.dispatch.build();
+    let simulation = Simulation::new(building, traffic);
+    let report = simulation.run(dispatch_strategy).await;
+
+    if args.iter().any(|a| a == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{dispatch_strategies::DispatchKind, elevator::{AVERAGE_PASSENGER_KG, Capacity}, elevator_pools::PoolKind, parking::ParkingPolicy, schedule::{Schedule, TimeOfDay}};
+
+/* Building model */
+/* 1. Floors are numbered from lowest_floor upwards, basements are negative */
+/* 2. Internally a floor is its position from the lowest floor, the lowest floor is 0 */
+/* 3. Loaded from a JSON file (`--config building.json`), CLI args override the file */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildingConfig {
+    pub floors: usize,
+    pub lowest_floor: i32,
+    pub elevators: usize,
+    pub floor_labels: Vec<String>, // lowest floor first, missing labels fall back to the floor number
+    pub car_capacity: Capacity,    // the same for every car
+    pub recall_floor: Option<i32>, // where cars go on fire recall, the entrance floor when unset
+
+    pub idle_pool: PoolKind,
+    pub moving_pool: PoolKind,
+    pub dispatch: DispatchKind,
+    pub parking: ParkingPolicy,
+    pub schedule: Schedule,
+}
+
+impl Default for BuildingConfig {
+    fn default() -> Self {
+        BuildingConfig {
+            floors: 5,
+            lowest_floor: 0,
+            elevators: 3,
+            floor_labels: Vec::new(),
+            car_capacity: Capacity::default(),
+            recall_floor: None,
+            idle_pool: PoolKind::Stack,
+            moving_pool: PoolKind::Heap,
+            dispatch: DispatchKind::default(),
+            parking: ParkingPolicy::default(),
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+impl BuildingConfig {
+    pub fn from_file(path: &Path) -> Result<BuildingConfig, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let config: BuildingConfig = serde_json::from_str(&content).map_err(|e| format!("invalid building config {}: {}", path.display(), e))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /* `--config <file>`, then `--floors`, `--lowest-floor`, `--elevators`, `--capacity`, `--capacity-kg`, `--recall-floor`, `--idle-pool`, `--moving-pool`, `--dispatch`, `--parking` and `--clock-start` */
+    pub fn from_args(args: &[String]) -> Result<BuildingConfig, String> {
+        let value_of = |flag: &str| -> Option<&String> {
+            args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1))
+        };
+
+        let mut config = match value_of("--config") {
+            Some(path) => BuildingConfig::from_file(Path::new(path))?,
+            None => BuildingConfig::default(),
+        };
+
+        if let Some(floors) = value_of("--floors") {
+            config.floors = floors.parse().map_err(|_| format!("invalid --floors {}", floors))?;
+        }
+
+        if let Some(lowest_floor) = value_of("--lowest-floor") {
+            config.lowest_floor = lowest_floor.parse().map_err(|_| format!("invalid --lowest-floor {}", lowest_floor))?;
+        }
+
+        if let Some(elevators) = value_of("--elevators") {
+            config.elevators = elevators.parse().map_err(|_| format!("invalid --elevators {}", elevators))?;
+        }
+
+        if let Some(persons) = value_of("--capacity") {
+            config.car_capacity.persons = persons.parse().map_err(|_| format!("invalid --capacity {}", persons))?;
+        }
+
+        if let Some(kg) = value_of("--capacity-kg") {
+            config.car_capacity.kg = Some(kg.parse().map_err(|_| format!("invalid --capacity-kg {}", kg))?);
+        }
+
+        if let Some(floor) = value_of("--recall-floor") {
+            config.recall_floor = Some(floor.parse().map_err(|_| format!("invalid --recall-floor {}", floor))?);
+        }
+
+        if let Some(name) = value_of("--idle-pool") {
+            config.idle_pool = PoolKind::from_name(name).ok_or(format!("unknown pool {}", name))?;
+        }
+
+        if let Some(name) = value_of("--moving-pool") {
+            config.moving_pool = PoolKind::from_name(name).ok_or(format!("unknown pool {}", name))?;
+        }
+
+        if let Some(name) = value_of("--dispatch") {
+            config.dispatch = DispatchKind::from_name(name).ok_or(format!("unknown dispatch strategy {}", name))?;
+        }
+
+        if let Some(name) = value_of("--parking") {
+            config.parking = ParkingPolicy::from_name(name).ok_or(format!("unknown parking policy {}", name))?;
+        }
+
+        if let Some(time) = value_of("--clock-start") {
+            config.schedule.clock_start = TimeOfDay::parse(time).ok_or(format!("invalid --clock-start {}, use HH:MM", time))?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.floors == 0 {
+            return Err("a building needs at least one floor".to_string());
+        }
+
+        if self.elevators == 0 {
+            return Err("a building needs at least one elevator".to_string());
+        }
+
+        if !self.car_capacity_fits_one() {
+            return Err("a car must fit at least one passenger".to_string());
+        }
+
+        if let Some(floor) = self.recall_floor.filter(|floor| self.floor_index(*floor).is_none()) {
+            return Err(format!("recall floor {} is not in the building", floor));
+        }
+
+        if self.floor_labels.len() > self.floors {
+            return Err(format!("{} floor labels given for {} floors", self.floor_labels.len(), self.floors));
+        }
+
+        self.schedule.validate()?;
+
+        Ok(())
+    }
+
+    fn car_capacity_fits_one(&self) -> bool {
+        self.car_capacity.persons > 0 && self.car_capacity.kg.is_none_or(|kg| kg >= AVERAGE_PASSENGER_KG)
+    }
+
+    pub fn highest_floor(&self) -> i32 {
+        self.lowest_floor + self.floors as i32 - 1
+    }
+
+    /* building floor number -> internal floor, None when the building has no such floor */
+    pub fn floor_index(&self, floor: i32) -> Option<usize> {
+        if floor < self.lowest_floor || floor > self.highest_floor() {
+            return None;
+        }
+
+        Some((floor - self.lowest_floor) as usize)
+    }
+
+    pub fn contains(&self, floor: usize) -> bool {
+        floor < self.floors
+    }
+
+    /* where visitors enter the building, the ground floor if there is one */
+    pub fn entrance_floor(&self) -> usize {
+        self.floor_index(0).unwrap_or(0)
+    }
+
+    pub fn recall_floor_index(&self) -> usize {
+        self.recall_floor.and_then(|floor| self.floor_index(floor)).unwrap_or(self.entrance_floor())
+    }
+
+    pub fn floor_label(&self, floor: usize) -> String {
+        match self.floor_labels.get(floor) {
+            Some(label) => label.clone(),
+            None => (self.lowest_floor + floor as i32).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* ten floors, two of them basements, floors -2 to 7 */
+    fn with_basements() -> BuildingConfig {
+        BuildingConfig { floors: 10, lowest_floor: -2, ..BuildingConfig::default() }
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        std::iter::once("elevator").chain(line.split_whitespace()).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn floors_are_counted_from_the_lowest_basement() {
+        let building = with_basements();
+
+        assert_eq!(building.highest_floor(), 7);
+        assert_eq!(building.floor_index(-2), Some(0));
+        assert_eq!(building.floor_index(0), Some(2));
+        assert_eq!(building.floor_index(7), Some(9));
+        assert_eq!(building.floor_index(-3), None);
+        assert_eq!(building.floor_index(8), None);
+        assert_eq!(building.floor_label(0), "-2");
+    }
+
+    #[test]
+    fn the_entrance_is_the_ground_floor_when_there_is_one() {
+        assert_eq!(with_basements().entrance_floor(), 2);
+        assert_eq!(with_basements().recall_floor_index(), 2);
+
+        /* a building starting above the ground enters at its lowest floor */
+        assert_eq!(BuildingConfig { lowest_floor: 3, ..BuildingConfig::default() }.entrance_floor(), 0);
+    }
+
+    #[test]
+    fn a_valid_building_passes() {
+        assert_eq!(BuildingConfig::default().validate(), Ok(()));
+        assert_eq!(BuildingConfig { recall_floor: Some(-2), ..with_basements() }.validate(), Ok(()));
+    }
+
+    #[test]
+    fn impossible_buildings_are_rejected() {
+        let invalid = [
+            BuildingConfig { floors: 0, ..BuildingConfig::default() },
+            BuildingConfig { elevators: 0, ..BuildingConfig::default() },
+            BuildingConfig { car_capacity: Capacity { persons: 0, kg: None }, ..BuildingConfig::default() },
+            BuildingConfig { car_capacity: Capacity { persons: 4, kg: Some(AVERAGE_PASSENGER_KG - 1) }, ..BuildingConfig::default() },
+            BuildingConfig { recall_floor: Some(-3), ..with_basements() },
+            BuildingConfig { floor_labels: vec!["B".to_string(); 11], ..with_basements() },
+        ];
+
+        for building in invalid {
+            assert!(building.validate().is_err(), "{:?}", building);
+        }
+    }
+
+    #[test]
+    fn args_override_the_defaults() {
+        let building = BuildingConfig::from_args(&args("--floors 12 --lowest-floor -1 --elevators 4 --capacity 10 --recall-floor 0 --dispatch destination --parking lobby")).unwrap();
+
+        assert_eq!(building.floors, 12);
+        assert_eq!(building.lowest_floor, -1);
+        assert_eq!(building.elevators, 4);
+        assert_eq!(building.car_capacity.persons, 10);
+        assert_eq!(building.recall_floor_index(), 1);
+        assert_eq!(building.dispatch, DispatchKind::Destination);
+        assert_eq!(building.parking, ParkingPolicy::Lobby);
+    }
+
+    #[test]
+    fn args_override_the_config_file() {
+        let path = std::env::temp_dir().join(format!("building-config-test-{}.json", std::process::id()));
+        fs::write(&path, r#"{"floors": 20, "lowest_floor": -3, "elevators": 6}"#).unwrap();
+
+        let building = BuildingConfig::from_args(&args(&format!("--config {} --elevators 2", path.display())));
+        fs::remove_file(&path).unwrap();
+
+        let building = building.unwrap();
+        assert_eq!(building.floors, 20);
+        assert_eq!(building.lowest_floor, -3);
+        assert_eq!(building.elevators, 2);
+    }
+
+    #[test]
+    fn bad_args_are_rejected() {
+        for line in ["--floors many", "--floors 0", "--elevators -1", "--dispatch fastest", "--idle-pool list", "--parking roof", "--recall-floor 9", "--clock-start 25:00", "--config /nonexistent/building.json"] {
+            assert!(BuildingConfig::from_args(&args(line)).is_err(), "{}", line);
+        }
+    }
+}
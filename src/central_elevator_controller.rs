@@ -1,32 +1,188 @@
-use std::thread::sleep;
-use std::time::{self};
-use std::{collections::HashMap, fmt::Error, sync::Arc};
+use std::{collections::{BTreeSet, HashMap, HashSet, VecDeque}, fmt, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
 
-use crate::elevator::ElevatorState;
-use crate::elevator_controller::ElevatorController;
-use crate::elevator_pools::elevator_queue::ElevatorQueue;
+use crate::building_config::BuildingConfig;
+use crate::dispatch_error::DispatchError;
+use crate::dispatch_strategies::{CarPlan, FleetSnapshot, eta};
+use crate::door::{DOOR_CYCLE, DoorCommand};
+use crate::elevator::{Direction, ElevatorState, car_label};
+use crate::elevator_controller::{ElevatorController, HEARTBEAT_EVERY};
+use crate::elevator_pools::AnyElevatorPool;
 use crate::interfaces::CentralElevatorControllerI;
+use crate::interfaces::Clock;
+use crate::interfaces::DispatchStrategy;
 use crate::interfaces::ElevatorPool;
-use tokio::sync::{Mutex, Semaphore};
+use crate::parking::ParkingPolicy;
+use crate::schedule::{Schedule, ScheduledModes, TimeOfDay};
+use crate::traffic::{self, TrafficReport};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use tokio::sync::broadcast::Sender;
 use tokio::sync::broadcast::{Receiver, channel};
+use tokio::sync::broadcast::error::RecvError;
+use std::time::Duration;
+use uuid::Uuid;
 
-#[derive(Clone)]
+const SUPERVISE_EVERY: Duration = Duration::from_secs(5);
+const SILENT_AFTER: Duration = Duration::from_secs(3 * HEARTBEAT_EVERY.as_secs()); /* no heartbeat and no state for this long */
+const STALL_AFTER: Duration = Duration::from_secs(4 * DOOR_CYCLE.as_secs()); /* with work to do, no state for this long */
+const CALL_HISTORY: Duration = Duration::from_secs(15 * 60); /* how far back the learned parking policy looks */
+const TRAFFIC_EVERY: Duration = Duration::from_secs(30);
+const SCHEDULE_EVERY: Duration = Duration::from_secs(30);
+
+/* A hall call, either a destination request (from and to) or an up/down hall button (from and direction) */
+#[derive(Debug, Clone)]
 pub struct ElevatorRequest {
+    pub id: String, /* uuid, the same for the whole life of the hall call */
     pub from: usize,
-    pub to: usize,
+    pub to: Option<usize>, /* None for a hall button, the passenger presses a car call once in */
+    pub direction: Direction, /* Up or Down, the way the caller travels */
+}
+
+impl fmt::Display for ElevatorRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to {
+            Some(to) => write!(f, "{} -> {}", self.from, to),
+            None => write!(f, "{} {:?}", self.from, self.direction),
+        }
+    }
+}
+
+/* Where a hall call stands, serialized as {"status": "assigned", "elevator_id": 1} */
+/* A hall button call is done once boarded, where the passenger goes is a car call */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "elevator_id", rename_all = "snake_case")]
+pub enum RequestStatus {
+    Queued,             /* waiting for a car with room */
+    Assigned(usize),
+    CarArriving(usize), /* the car is on its way to stop at the caller's floor */
+    Boarded(usize),
+    Arrived(usize),
+    Cancelled,
+}
+
+impl RequestStatus {
+    pub fn elevator_id(&self) -> Option<usize> {
+        match self {
+            RequestStatus::Assigned(id) | RequestStatus::CarArriving(id) | RequestStatus::Boarded(id) | RequestStatus::Arrived(id) => Some(*id),
+            RequestStatus::Queued | RequestStatus::Cancelled => None,
+        }
+    }
+}
+
+/* A hall call as the caller sees it, floors are building floor numbers */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HallCall {
+    pub request_id: String,
+    pub from: i32,
+    pub to: Option<i32>, /* null for a hall button */
+    pub direction: Direction,
+    pub car: Option<String>, /* label shown to the caller, e.g. "B" for elevator 1, "AA" for elevator 26, null until a car is assigned */
+    pub eta_secs: Option<f64>, /* estimated wait for the assigned car when it was assigned */
+    #[serde(flatten)]
+    pub status: RequestStatus,
+    pub updated_secs: f64, /* clock time of the last status change */
+}
+
+/* What the central controller tells an elevator controller */
+#[derive(Debug, Clone)]
+pub enum ElevatorSignal {
+    Request(ElevatorRequest),
+    Cancel(String), /* request id, the passenger no longer wants to travel */
+    OutOfService(Option<usize>), /* finish the riders, or drop everyone off at this floor, then park */
+    InService,
+    Recall(usize),          /* fire recall phase I, drop every call and park at this floor */
+    EndRecall,
+    FirefighterGoTo(usize), /* fire recall phase II, only firefighters move the car */
+    FirefighterDoor(bool),  /* true opens the doors */
+    Door(DoorCommand),      /* a door button inside the car or the door edge sensor */
+    CarCall(usize, Direction), /* a floor pressed inside the car, and which way it is from where the car is */
+    Park(usize),            /* an idle car moves to this home floor, dropped on the next call */
+}
+
+/* A hall call as it was registered, kept for a while to learn where calls come from */
+#[derive(Debug, Clone, Copy)]
+pub struct CallRecord {
+    pub at: Duration, /* clock time */
+    pub from: usize,
+    pub to: Option<usize>,
+    pub direction: Direction,
+}
+
+/* The cars currently in the building, sent to SSE clients whenever it changes */
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetLayout {
+    pub elevators: Vec<usize>, /* ids, ascending */
+}
+
+/* Building-wide fire service, set while a recall is active */
+#[derive(Debug, Clone, Copy)]
+struct FireService {
+    recall_floor: usize,
+    phase_two_elevator: Option<usize>, /* the single car under firefighter control */
+}
+
+/* What an elevator controller tells the central controller besides its state */
+#[derive(Debug, Clone)]
+pub enum ElevatorEvent {
+    HandBack(usize, ElevatorRequest), /* elevator id, a full car could not take this hall call */
+    Progress(String, RequestStatus),  /* request id, the car reached the next step of the call */
+    Heartbeat(usize),                 /* elevator id, its signal loop is alive */
+}
+
+/* What the supervisor knows about a car, serialized for the admin API */
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CarHealth {
+    pub elevator_id: usize,
+    pub faulted: bool, /* stopped reporting or stalled with work to do, out of dispatch until put back in service */
+    pub last_heartbeat_secs: f64,
+    pub last_state_secs: f64,
+    #[serde(skip)]
+    busy_since: Option<Duration>, /* when the supervisor first saw it with work */
 }
 
 /* Elevator controller */
 /* 1. Hold all the elevator controllers */
 /* 2. Stores elevators based on their respective state */
+/* 3. Asks the dispatch strategy which elevator serves a hall call, car calls go straight to the car they were pressed in */
 #[derive(Debug)]
 pub struct CentralElevatorController {
-    moving_up_elevators: Mutex<ElevatorQueue>,
-    moving_down_elevators: Mutex<ElevatorQueue>,
-    idle_elevators: Mutex<ElevatorQueue>,
-    permits: Mutex<Semaphore>,
-    signal_transmitter: HashMap<usize, Sender<ElevatorRequest>>,
+    moving_up_elevators: Mutex<AnyElevatorPool>,
+    moving_down_elevators: Mutex<AnyElevatorPool>,
+    idle_elevators: Mutex<AnyElevatorPool>,
+    fleet: Mutex<HashMap<usize, ElevatorState>>, /* latest state of every elevator */
+
+    /* cars in none of the pools, they take no hall calls */
+    out_of_service: Mutex<HashSet<usize>>,
+    decommissioning: Mutex<HashSet<usize>>, /* leave the fleet once parked */
+    health: Mutex<HashMap<usize, CarHealth>>, /* the supervisor faults cars that go silent or stall */
+    fire_service: Mutex<Option<FireService>>, /* every car parked at the recall floor, hall calls refused */
+
+    car_calls: Mutex<HashMap<usize, BTreeSet<usize>>>, /* floors pressed inside each car, until the car opens there */
+    building: BuildingConfig,
+    clock: Arc<dyn Clock>,
+    dispatch_strategy: Box<dyn DispatchStrategy>,
+    pending: Mutex<VecDeque<ElevatorRequest>>, /* hall calls waiting for a car, oldest first, retried on every state change */
+
+    /* where idle cars wait, a detected or scheduled peak overrides the policy */
+    parking: Mutex<ParkingPolicy>,
+    parked: Mutex<HashMap<usize, usize>>, /* idle car -> home floor it was sent to or waits at */
+    call_history: Mutex<VecDeque<CallRecord>>, /* hall calls of the last CALL_HISTORY, oldest first */
+    traffic: Mutex<TrafficReport>,
+    traffic_tx: Sender<TrafficReport>, /* every change of traffic mode */
+
+    /* time-of-day rules for the traffic mode, the parking policy and the cars in service */
+    schedule: Mutex<Schedule>,
+    scheduled: Mutex<ScheduledModes>, /* what the schedule applied last */
+    scheduled_off: Mutex<HashSet<usize>>, /* cars the schedule took out of service, it puts them back */
+
+    requests: Mutex<HashMap<String, HallCall>>, /* request id -> latest status */
+    request_tx: Sender<HallCall>, /* every status change, e.g. for the SSE stream */
+
+    /* cars are added and removed at runtime */
+    fleet_tx: Sender<FleetLayout>, /* every car added or removed */
+    next_elevator_id: AtomicUsize, /* ids of removed cars are not reused */
+    signal_transmitter: Mutex<HashMap<usize, Sender<ElevatorSignal>>>,
+    event_tx: Sender<ElevatorEvent>, /* handed to every new car, full cars hand back hall calls through it */
     global_state_tx: Sender<ElevatorState>
 }
 
@@ -38,170 +194,1032 @@ impl CentralElevatorController {
             match bind.recv().await {
                 Ok(state) => {
                     // let mut elevator_controller: Option<ElevatorController> = None;
-                    println!("STATE: {} floor {} from {} to {}", state.id, state.current_floor, state.initial_direction, state.direction);
+                    log!("STATE [{:.1}s]: {} floor {} from {:?} to {:?}", self.clock.now().as_secs_f64(), state.id, state.current_floor, state.initial_direction, state.direction);
+
+                    /* decommissioned, a worker of the car may still be finishing */
+                    /* checked under the lock remove_car takes first, so a removed car never comes back into the fleet */
+                    {
+                        let transmitters = self.signal_transmitter.lock().await;
+                        if !transmitters.contains_key(&state.id) {
+                            continue;
+                        }
+
+                        self.fleet.lock().await.insert(state.id, state.clone());
+                    }
 
                     let _ = self.global_state_tx.send(state.clone());
 
-                    if state.direction.as_str() == state.initial_direction.as_str() && state.direction.as_str() != "idle" {
+                    let faulted = match self.health.lock().await.get_mut(&state.id) {
+                        Some(health) => {
+                            health.last_state_secs = self.clock.now().as_secs_f64();
+                            health.faulted
+                        }
+                        None => false,
+                    };
+
+                    /* the car opened at a floor pressed inside it */
+                    if state.is_door_open && let Some(car_calls) = self.car_calls.lock().await.get_mut(&state.id) {
+                        car_calls.remove(&state.current_floor);
+                    }
+
+                    /* a decommissioned car leaves the fleet once its riders are out */
+                    if state.direction == Direction::OutOfService && self.decommissioning.lock().await.contains(&state.id) {
+                        self.remove_car(state.id).await;
                         continue;
                     }
 
-                    /* adjust elevator state */
-                    match state.direction.as_str() {
-                        "up" => {
-                            let _ = self.moving_up_elevators.lock().await.insert_elevator(state.clone()).await;
-                        },
-                        "down" => {
-                            let _ = self.moving_down_elevators.lock().await.insert_elevator(state.clone()).await;
-                        },
-                        "idle" => {
-                            println!("inserted to idle {}", state.id);
-                            let _ = self.idle_elevators.lock().await.insert_elevator(state.clone()).await;
-                        },
-                        &_ =>{}
+                    /* finishing its riders, parked, recalled or faulted, either way not dispatchable */
+                    if faulted || self.out_of_service.lock().await.contains(&state.id) || self.fire_service.lock().await.is_some() {
+                        continue;
                     }
 
-                    match state.initial_direction.as_str() {
-                        "up" => {
+                    if state.direction == state.initial_direction && state.direction != Direction::Idle {
+                        /* same direction, but the car may have room again */
+                        self.drain_pending().await;
+                        continue;
+                    }
+
+                    /* adjust elevator state, leave the old pool first so an idle car reporting idle again is refreshed, not dropped */
+                    match state.initial_direction {
+                        Direction::Up => {
                             let _ = self.moving_up_elevators.lock().await.remove_elevator(state.id).await;
                         },
-                        "down" => {
+                        Direction::Down => {
                             let _ = self.moving_down_elevators.lock().await.remove_elevator(state.id).await;
                         },
-                        "idle" => {
-                            println!("inserted to idle {}", state.id);
+                        Direction::Idle => {
+                            log!("removed from idle {}", state.id);
                             let _ = self.idle_elevators.lock().await.remove_elevator(state.id).await;
                         },
-                        &_ =>{}
+                        Direction::Stopped | Direction::OutOfService => {}
+                    }
+
+                    match state.direction {
+                        Direction::Up => {
+                            let _ = self.moving_up_elevators.lock().await.insert_elevator(state.clone()).await;
+                        },
+                        Direction::Down => {
+                            let _ = self.moving_down_elevators.lock().await.insert_elevator(state.clone()).await;
+                        },
+                        Direction::Idle => {
+                            log!("inserted to idle {}", state.id);
+                            let _ = self.idle_elevators.lock().await.insert_elevator(state.clone()).await;
+                        },
+                        /* not dispatchable, stays out of every pool */
+                        Direction::Stopped | Direction::OutOfService => {}
                     }
 
+                    /* removed meanwhile, its pools may have been cleared before the insert above */
+                    if !self.signal_transmitter.lock().await.contains_key(&state.id) {
+                        self.take_elevator(state.id).await;
+                        continue;
+                    }
+
+                    self.drain_pending().await;
+
+                    /* nothing pending took it */
+                    if state.direction == Direction::Idle && self.is_idle(state.id).await {
+                        self.park(state.id, state.current_floor).await;
+                    }
                 }
+                /* the car was removed */
+                Err(RecvError::Closed) => break,
                 Err(_) => {
-                    println!("Failed to get elevator state");
+                    log!("Failed to get elevator state");
                 }
             }
-
-            sleep(time::Duration::from_secs(1));
         }
     }
 
-    pub async fn new(global_state_tx : Sender<ElevatorState>, no_of_elevator: usize) -> Arc<CentralElevatorController> {
-        /* Elevator containers */
-        let mut idle_elevators = ElevatorQueue::new();
+    /* hall calls a full car handed back are dispatched again, progress is recorded */
+    pub async fn listen_elevator_events(&self, event_receiver: Receiver<ElevatorEvent>) {
+        let mut bind = event_receiver;
 
-        let mut signal_transmitter: HashMap<usize, Sender<ElevatorRequest>> = HashMap::new();
-        let mut state_receivers: Vec<Receiver<ElevatorState>> = Vec::new();
+        loop {
+            match bind.recv().await {
+                Ok(ElevatorEvent::HandBack(elevator_id, request)) => {
+                    /* cancelled meanwhile, or already given to another car after a fault */
+                    if !self.is_owner(&request.id, elevator_id).await {
+                        continue;
+                    }
 
-        let mut permits_size:usize = 0;
+                    self.reassign(request).await;
+                }
+                Ok(ElevatorEvent::Progress(request_id, status)) => {
+                    /* a faulted car coming back to life does not speak for calls it lost */
+                    if let Some(elevator_id) = status.elevator_id() && !self.is_owner(&request_id, elevator_id).await {
+                        continue;
+                    }
+                    self.update_request(&request_id, status).await;
+                }
+                Ok(ElevatorEvent::Heartbeat(elevator_id)) => {
+                    if let Some(health) = self.health.lock().await.get_mut(&elevator_id) {
+                        health.last_heartbeat_secs = self.clock.now().as_secs_f64();
+                    }
+                }
+                Err(_) => {
+                    log!("Failed to get elevator event");
+                }
+            }
+        }
+    }
+
+    pub async fn new(global_state_tx : Sender<ElevatorState>, building: BuildingConfig, dispatch_strategy: Box<dyn DispatchStrategy>, clock: Arc<dyn Clock>) -> Arc<CentralElevatorController> {
+        /* shared by every elevator, hall calls they could not take and passenger progress */
+        let (event_tx, event_rx): (Sender<ElevatorEvent>, Receiver<ElevatorEvent>) = channel(256);
+        let parking = building.parking;
+        let schedule = building.schedule.clone();
+
+        let controller = Arc::new(CentralElevatorController {
+            moving_down_elevators: Mutex::new(building.moving_pool.build()),
+            moving_up_elevators: Mutex::new(building.moving_pool.build()),
+            idle_elevators: Mutex::new(building.idle_pool.build()),
+            fleet: Mutex::new(HashMap::new()),
+            out_of_service: Mutex::new(HashSet::new()),
+            decommissioning: Mutex::new(HashSet::new()),
+            car_calls: Mutex::new(HashMap::new()),
+            health: Mutex::new(HashMap::new()),
+            fire_service: Mutex::new(None),
+            building,
+            clock,
+            dispatch_strategy,
+            pending: Mutex::new(VecDeque::new()),
+            parking: Mutex::new(parking),
+            parked: Mutex::new(HashMap::new()),
+            call_history: Mutex::new(VecDeque::new()),
+            traffic: Mutex::new(TrafficReport::default()),
+            traffic_tx: channel(16).0,
+            schedule: Mutex::new(schedule),
+            scheduled: Mutex::new(ScheduledModes::default()),
+            scheduled_off: Mutex::new(HashSet::new()),
+            requests: Mutex::new(HashMap::new()),
+            request_tx: channel(256).0,
+            fleet_tx: channel(16).0,
+            next_elevator_id: AtomicUsize::new(0),
+            signal_transmitter: Mutex::new(HashMap::new()),
+            event_tx,
+            global_state_tx
+        });
 
         /* Building elevators */
-        for i in 0..no_of_elevator {
-            let (state_tx, state_rx): (Sender<ElevatorState>, Receiver<ElevatorState>) = channel(1);
-            state_receivers.insert(i, state_rx);
+        for _ in 0..controller.building.elevators {
+            let _ = controller.add_car().await;
+        }
 
-            let (signal_tx, signal_rx): (Sender<ElevatorRequest>, Receiver<ElevatorRequest>) = channel(10);
-            signal_transmitter.insert(i, signal_tx);
+        let bind_controller = controller.clone();
+        tokio::spawn(async move {
+            bind_controller.listen_elevator_events(event_rx).await;
+        });
 
-            /* Runner for receiving requests from central controller */
-            tokio::spawn(async move {
-                let elevator_controller = ElevatorController::new(i, state_tx);
-                elevator_controller.listen_request(signal_rx).await;
-            });
+        let bind_controller = controller.clone();
+        tokio::spawn(async move {
+            bind_controller.supervise().await;
+        });
 
-            /* Put the elevator to idles elevator */
-            let _ = idle_elevators
-                .insert_elevator(ElevatorState {
-                    id: i,
-                    current_floor: 0,
-                    current_load: 0,
-                    direction: "idle".to_string(),
-                    initial_direction: "idle".to_string(),
-                    is_door_open: false,
-                    is_moving: false,
-                })
-                .await;
+        let bind_controller = controller.clone();
+        tokio::spawn(async move {
+            bind_controller.watch_traffic().await;
+        });
 
-            permits_size +=1;
+        let bind_controller = controller.clone();
+        tokio::spawn(async move {
+            bind_controller.run_schedule().await;
+        });
+
+        controller
+    }
+
+    /* spawn a new car, idle at the lowest floor, returns its id */
+    pub async fn add_car(self: &Arc<Self>) -> Result<usize, DispatchError> {
+        if self.fire_service.lock().await.is_some() {
+            return Err(DispatchError::FireRecallActive);
         }
 
+        let id = self.next_elevator_id.fetch_add(1, Ordering::Relaxed);
+        let (state_tx, state_rx): (Sender<ElevatorState>, Receiver<ElevatorState>) = channel(16);
+        let (signal_tx, signal_rx): (Sender<ElevatorSignal>, Receiver<ElevatorSignal>) = channel(10);
 
-        let controller = Arc::new(CentralElevatorController {
-            moving_down_elevators: Mutex::new(ElevatorQueue::new()),
-            moving_up_elevators: Mutex::new(ElevatorQueue::new()),
-            idle_elevators: Mutex::new(idle_elevators),
-            signal_transmitter: signal_transmitter,
-            permits: Mutex::new(Semaphore::new(permits_size)),
-            global_state_tx: global_state_tx
+        /* Runner for receiving requests from central controller */
+        let elevator_controller = ElevatorController::new(id, self.building.car_capacity, state_tx, self.event_tx.clone(), self.clock.clone());
+        tokio::spawn(async move {
+            elevator_controller.listen_request(signal_rx).await;
         });
 
-        for state_rx in state_receivers {
-            let bind_controller = controller.clone();
-            let bind_rx = state_rx;
-            tokio::spawn(async move {
-                bind_controller.listen_elevator_state(bind_rx).await;
-            });
+        let bind_controller = self.clone();
+        tokio::spawn(async move {
+            bind_controller.listen_elevator_state(state_rx).await;
+        });
+
+        /* Put the elevator to idles elevator */
+        let state = ElevatorState::new(id, self.building.car_capacity);
+        let now = self.clock.now().as_secs_f64();
+        self.fleet.lock().await.insert(id, state.clone());
+        self.health.lock().await.insert(id, CarHealth { elevator_id: id, faulted: false, last_heartbeat_secs: now, last_state_secs: now, busy_since: None });
+        self.signal_transmitter.lock().await.insert(id, signal_tx);
+        let _ = self.idle_elevators.lock().await.insert_elevator(state).await;
+
+        log!("Elevator {} added", id);
+        self.announce_fleet().await;
+        self.drain_pending().await;
+        Ok(id)
+    }
+
+    /* the car stops taking calls, finishes its riders, then leaves the fleet */
+    pub async fn decommission_car(&self, elevator_id: usize) -> Result<(), DispatchError> {
+        if self.fire_service.lock().await.is_some() {
+            return Err(DispatchError::FireRecallActive);
+        }
+
+        self.transmitter(elevator_id).await?;
+
+        /* cars already leaving do not count */
+        let staying = self.signal_transmitter.lock().await.len() - self.decommissioning.lock().await.len();
+        if !self.decommissioning.lock().await.insert(elevator_id) {
+            return Ok(());
+        }
+
+        if staying <= 1 {
+            self.decommissioning.lock().await.remove(&elevator_id);
+            return Err(DispatchError::LastCar(elevator_id));
+        }
+
+        /* a faulted car will not drain, it goes right away */
+        if self.is_faulted(elevator_id).await {
+            self.remove_car(elevator_id).await;
+            return Ok(());
+        }
+
+        /* removed once it reports parked */
+        self.take_out_of_service(elevator_id, None).await
+    }
+
+    /* dropping its signal sender ends the car's tasks */
+    async fn remove_car(&self, elevator_id: usize) {
+        if self.signal_transmitter.lock().await.remove(&elevator_id).is_none() {
+            return;
+        }
+
+        self.take_elevator(elevator_id).await;
+        self.fleet.lock().await.remove(&elevator_id);
+        self.health.lock().await.remove(&elevator_id);
+        self.car_calls.lock().await.remove(&elevator_id);
+        self.out_of_service.lock().await.remove(&elevator_id);
+        self.decommissioning.lock().await.remove(&elevator_id);
+        self.scheduled_off.lock().await.remove(&elevator_id);
+
+        log!("Elevator {} decommissioned", elevator_id);
+        self.announce_fleet().await;
+    }
+
+    /* ids of every car, decommissioning ones included until they are gone */
+    pub async fn fleet_layout(&self) -> FleetLayout {
+        let mut elevators: Vec<usize> = self.signal_transmitter.lock().await.keys().copied().collect();
+        elevators.sort();
+        FleetLayout { elevators }
+    }
+
+    pub fn subscribe_fleet(&self) -> Receiver<FleetLayout> {
+        self.fleet_tx.subscribe()
+    }
+
+    async fn announce_fleet(&self) {
+        let _ = self.fleet_tx.send(self.fleet_layout().await);
+    }
+
+    async fn transmitter(&self, elevator_id: usize) -> Result<Sender<ElevatorSignal>, DispatchError> {
+        match self.signal_transmitter.lock().await.get(&elevator_id) {
+            None => Err(DispatchError::UnknownCar(elevator_id)),
+            Some(tx) if tx.receiver_count() == 0 => Err(DispatchError::CarUnreachable(elevator_id)),
+            Some(tx) => Ok(tx.clone()),
+        }
+    }
+
+    async fn transmitters(&self) -> Vec<(usize, Sender<ElevatorSignal>)> {
+        self.signal_transmitter.lock().await.iter().map(|(id, tx)| (*id, tx.clone())).collect()
+    }
+
+    pub fn building(&self) -> &BuildingConfig {
+        &self.building
+    }
+
+    async fn take_elevator(&self, elevator_id: usize) {
+        /* busy, parked out of service or gone, either way no longer holding a home floor */
+        self.parked.lock().await.remove(&elevator_id);
+
+        for pool in [&self.idle_elevators, &self.moving_up_elevators, &self.moving_down_elevators] {
+            let mut pool = pool.lock().await;
+
+            /* cheaper to pop when it is the one the pool would hand out anyway */
+            let is_next = pool.peek_elevator().await.is_some_and(|e| e.id == elevator_id);
+            if is_next {
+                let _ = pool.get_elevator().await;
+            } else {
+                let _ = pool.remove_elevator(elevator_id).await;
+            }
+        }
+    }
+
+    pub async fn hall_call(&self, request_id: &str) -> Result<HallCall, DispatchError> {
+        self.requests
+            .lock()
+            .await
+            .get(request_id)
+            .cloned()
+            .ok_or(DispatchError::UnknownRequest(request_id.to_string()))
+    }
+
+    /* every status change of every hall call from now on */
+    pub fn subscribe_requests(&self) -> Receiver<HallCall> {
+        self.request_tx.subscribe()
+    }
+
+    async fn update_request(&self, request_id: &str, status: RequestStatus) {
+        let mut requests = self.requests.lock().await;
+        let Some(hall_call) = requests.get_mut(request_id) else {
+            return;
+        };
+
+        /* a cancelled call stays cancelled, whatever the car reports afterwards */
+        if hall_call.status == RequestStatus::Cancelled {
+            return;
         }
 
-        return controller;
+        hall_call.status = status;
+        hall_call.car = status.elevator_id().map(car_label);
+        if status == RequestStatus::Queued {
+            hall_call.eta_secs = None;
+        }
+        hall_call.updated_secs = self.clock.now().as_secs_f64();
+        let _ = self.request_tx.send(hall_call.clone());
+    }
+
+    /* the hall call is currently in the hands of this car */
+    async fn is_owner(&self, request_id: &str, elevator_id: usize) -> bool {
+        self.requests.lock().await.get(request_id).is_some_and(|call| call.status.elevator_id() == Some(elevator_id))
+    }
+
+    /* a hall call some car gave up, another car takes it or it waits in the queue */
+    async fn reassign(&self, request: ElevatorRequest) {
+        if self.fire_service.lock().await.is_some() {
+            self.update_request(&request.id, RequestStatus::Cancelled).await;
+            return;
+        }
+
+        log!("reassigning {}", request);
+        if let Err(e) = self.dispatch(request.clone()).await {
+            log!("request {} waits for a car: {}", request.id, e);
+            self.update_request(&request.id, RequestStatus::Queued).await;
+            self.pending.lock().await.push_back(request);
+        }
+    }
+
+    pub async fn health(&self) -> Vec<CarHealth> {
+        let mut health: Vec<CarHealth> = self.health.lock().await.values().copied().collect();
+        health.sort_by_key(|h| h.elevator_id);
+        health
+    }
+
+    /* watch every car, a car that goes silent or stalls with work to do is faulted */
+    async fn supervise(&self) {
+        loop {
+            self.clock.sleep(SUPERVISE_EVERY).await;
+
+            let now = self.clock.now();
+            let plans = self.car_plans().await;
+            let mut faulted = Vec::new();
+
+            for health in self.health.lock().await.values_mut().filter(|h| !h.faulted) {
+                let has_work = plans.get(&health.elevator_id).is_some_and(|plan| !plan.stops.is_empty());
+                health.busy_since = match has_work {
+                    true => health.busy_since.or(Some(now)),
+                    false => None,
+                };
+
+                let since_state = now.saturating_sub(Duration::from_secs_f64(health.last_state_secs));
+                let since_heartbeat = now.saturating_sub(Duration::from_secs_f64(health.last_heartbeat_secs));
+
+                let silent = since_state.min(since_heartbeat) > SILENT_AFTER;
+                let stalled = since_state > STALL_AFTER && health.busy_since.is_some_and(|since| now - since > STALL_AFTER);
+
+                if silent || stalled {
+                    log!("Elevator {} faulted: {}", health.elevator_id, if silent { "silent" } else { "stalled" });
+                    health.faulted = true;
+                    faulted.push(health.elevator_id);
+                }
+            }
+
+            for elevator_id in faulted {
+                self.fault(elevator_id).await;
+            }
+        }
+    }
+
+    /* out of dispatch, every hall call not boarded yet goes to another car */
+    async fn fault(&self, elevator_id: usize) {
+        self.take_elevator(elevator_id).await;
+        self.car_calls.lock().await.remove(&elevator_id);
+
+        let owed: Vec<HallCall> = self
+            .requests
+            .lock()
+            .await
+            .values()
+            .filter(|call| matches!(call.status, RequestStatus::Assigned(id) | RequestStatus::CarArriving(id) if id == elevator_id))
+            .cloned()
+            .collect();
+
+        for hall_call in owed {
+            let (Some(from), to) = (self.building.floor_index(hall_call.from), hall_call.to.and_then(|to| self.building.floor_index(to))) else {
+                continue;
+            };
+
+            /* in case the car comes back, it must not pick the caller up as well */
+            if let Ok(tx) = self.transmitter(elevator_id).await {
+                let _ = tx.send(ElevatorSignal::Cancel(hall_call.request_id.clone()));
+            }
+
+            self.update_request(&hall_call.request_id, RequestStatus::Queued).await;
+            self.reassign(ElevatorRequest {
+                id: hall_call.request_id,
+                from,
+                to,
+                direction: hall_call.direction,
+            }).await;
+        }
+
+        /* it will not drain, riders still on board are lost with the car */
+        if self.decommissioning.lock().await.contains(&elevator_id) {
+            self.remove_car(elevator_id).await;
+        }
+    }
+
+    /* the car leaves every pool now, and parks once its riders are out */
+    pub async fn take_out_of_service(&self, elevator_id: usize, evacuate_to: Option<usize>) -> Result<(), DispatchError> {
+        let tx = self.transmitter(elevator_id).await?;
+        if let Some(floor) = evacuate_to.filter(|floor| !self.building.contains(*floor)) {
+            return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + floor as i32));
+        }
+
+        self.out_of_service.lock().await.insert(elevator_id);
+        self.take_elevator(elevator_id).await;
+        if evacuate_to.is_some() {
+            self.car_calls.lock().await.remove(&elevator_id);
+        }
+
+        tx.send(ElevatorSignal::OutOfService(evacuate_to)).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        Ok(())
+    }
+
+    /* the car reports idle again, which puts it back into idle_elevators */
+    pub async fn return_to_service(&self, elevator_id: usize) -> Result<(), DispatchError> {
+        let tx = self.transmitter(elevator_id).await?;
+
+        self.out_of_service.lock().await.remove(&elevator_id);
+        self.decommissioning.lock().await.remove(&elevator_id);
+        self.scheduled_off.lock().await.remove(&elevator_id);
+        if let Some(health) = self.health.lock().await.get_mut(&elevator_id) {
+            let now = self.clock.now().as_secs_f64();
+            health.faulted = false;
+            health.last_heartbeat_secs = now;
+            health.last_state_secs = now;
+            health.busy_since = None;
+        }
+
+        tx.send(ElevatorSignal::InService).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        Ok(())
+    }
+
+    /* conventional up/down button at a landing, the car that answers it is picked like for any hall call */
+    pub async fn press_hall_button(&self, floor: usize, direction: Direction) -> Result<HallCall, DispatchError> {
+        if !self.building.contains(floor) {
+            return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + floor as i32));
+        }
+
+        /* no up button on the top floor, no down button on the bottom one */
+        let has_button = match direction {
+            Direction::Up => floor + 1 < self.building.floors,
+            Direction::Down => floor > 0,
+            _ => false,
+        };
+        if !has_button {
+            return Err(DispatchError::NoHallButton(self.building.lowest_floor + floor as i32));
+        }
+
+        self.register_call(ElevatorRequest {
+            id: Uuid::new_v4().to_string(),
+            from: floor,
+            to: None,
+            direction,
+        }).await
+    }
+
+    /* a floor button inside the car, only that car serves it */
+    pub async fn press_car_call(&self, elevator_id: usize, floor: usize) -> Result<(), DispatchError> {
+        let tx = self.transmitter(elevator_id).await?;
+        if !self.building.contains(floor) {
+            return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + floor as i32));
+        }
+
+        /* firefighters drive recalled cars through their own commands */
+        if self.fire_service.lock().await.is_some() {
+            return Err(DispatchError::FireRecallActive);
+        }
+
+        if self.out_of_service.lock().await.contains(&elevator_id) {
+            return Err(DispatchError::CarOutOfService(elevator_id));
+        }
+
+        if self.is_faulted(elevator_id).await {
+            return Err(DispatchError::CarFaulted(elevator_id));
+        }
+
+        let current_floor = self.fleet.lock().await.get(&elevator_id).map(|elevator| elevator.current_floor).ok_or(DispatchError::CarMissing(elevator_id))?;
+        let direction = if floor < current_floor { Direction::Down } else { Direction::Up };
+
+        tx.send(ElevatorSignal::CarCall(floor, direction)).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        self.car_calls.lock().await.entry(elevator_id).or_default().insert(floor);
+        self.parked.lock().await.remove(&elevator_id);
+        Ok(())
+    }
+
+    /* door open, door close or an obstruction, cars under fire service ignore them */
+    pub async fn press_door_button(&self, elevator_id: usize, command: DoorCommand) -> Result<(), DispatchError> {
+        let tx = self.transmitter(elevator_id).await?;
+
+        tx.send(ElevatorSignal::Door(command)).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        Ok(())
+    }
+
+    /* phase I, every call is cancelled and every car parks at the recall floor */
+    pub async fn start_fire_recall(&self, recall_floor: Option<usize>) -> Result<(), DispatchError> {
+        let recall_floor = recall_floor.unwrap_or(self.building.recall_floor_index());
+        if !self.building.contains(recall_floor) {
+            return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + recall_floor as i32));
+        }
+
+        *self.fire_service.lock().await = Some(FireService { recall_floor, phase_two_elevator: None });
+        log!("FIRE RECALL to floor {}", recall_floor);
+
+        let pending: Vec<ElevatorRequest> = self.pending.lock().await.drain(..).collect();
+        for request in pending {
+            self.update_request(&request.id, RequestStatus::Cancelled).await;
+        }
+
+        self.car_calls.lock().await.clear();
+        for (id, tx) in self.transmitters().await {
+            self.take_elevator(id).await;
+            let _ = tx.send(ElevatorSignal::Recall(recall_floor));
+        }
+
+        Ok(())
+    }
+
+    /* cars go back to normal service, the ones taken out of service before stay out */
+    pub async fn end_fire_recall(&self) -> Result<(), DispatchError> {
+        if self.fire_service.lock().await.take().is_none() {
+            return Err(DispatchError::NoFireRecall);
+        }
+
+        log!("FIRE RECALL ended");
+        let out_of_service = self.out_of_service.lock().await.clone();
+        for (id, tx) in self.transmitters().await {
+            let _ = tx.send(ElevatorSignal::EndRecall);
+            if out_of_service.contains(&id) {
+                let _ = tx.send(ElevatorSignal::OutOfService(None));
+            }
+        }
+
+        Ok(())
+    }
+
+    /* phase II, the first car a firefighter moves is the only one they control */
+    pub async fn firefighter_go_to(&self, elevator_id: usize, floor: usize) -> Result<(), DispatchError> {
+        if !self.building.contains(floor) {
+            return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + floor as i32));
+        }
+
+        let tx = self.phase_two_transmitter(elevator_id, true).await?;
+        tx.send(ElevatorSignal::FirefighterGoTo(floor)).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        Ok(())
+    }
+
+    pub async fn firefighter_door(&self, elevator_id: usize, open: bool) -> Result<(), DispatchError> {
+        let tx = self.phase_two_transmitter(elevator_id, false).await?;
+        tx.send(ElevatorSignal::FirefighterDoor(open)).map_err(|_| DispatchError::ChannelClosed(elevator_id))?;
+        Ok(())
+    }
+
+    /* the signal channel of the car in phase II, `take_control` puts the car in phase II when no car is */
+    async fn phase_two_transmitter(&self, elevator_id: usize, take_control: bool) -> Result<Sender<ElevatorSignal>, DispatchError> {
+        let tx = self.transmitter(elevator_id).await?;
+
+        let mut fire_service = self.fire_service.lock().await;
+        let fire_service = fire_service.as_mut().ok_or(DispatchError::NoFireRecall)?;
+
+        match fire_service.phase_two_elevator {
+            Some(id) if id == elevator_id => Ok(tx),
+            Some(id) => Err(DispatchError::PhaseTwoInUse(id)),
+            None if take_control => {
+                log!("FIRE RECALL phase II on elevator {}, recalled to {}", elevator_id, fire_service.recall_floor);
+                fire_service.phase_two_elevator = Some(elevator_id);
+                Ok(tx)
+            }
+            None => Err(DispatchError::NoFireRecall),
+        }
+    }
+
+    /* drop a hall call, whether it still waits for a car, waits for its car or already rides it */
+    pub async fn cancel_request(&self, request_id: &str) -> Result<HallCall, DispatchError> {
+        let hall_call = self.hall_call(request_id).await?;
+
+        match hall_call.status {
+            RequestStatus::Queued => {
+                self.pending.lock().await.retain(|r| r.id != request_id);
+            }
+            /* the hall button was answered, the passenger is on its way */
+            RequestStatus::Boarded(_) if hall_call.to.is_none() => {
+                return Err(DispatchError::RequestFinished(request_id.to_string()));
+            }
+            RequestStatus::Assigned(id) | RequestStatus::CarArriving(id) | RequestStatus::Boarded(id) => {
+                let tx = self.transmitter(id).await?;
+                tx.send(ElevatorSignal::Cancel(request_id.to_string())).map_err(|_| DispatchError::ChannelClosed(id))?;
+            }
+            RequestStatus::Arrived(_) | RequestStatus::Cancelled => {
+                return Err(DispatchError::RequestFinished(request_id.to_string()));
+            }
+        }
+
+        self.update_request(request_id, RequestStatus::Cancelled).await;
+        self.hall_call(request_id).await
+    }
+
+    /* record a new hall call, then dispatch it or queue it until a car has room */
+    async fn register_call(&self, request: ElevatorRequest) -> Result<HallCall, DispatchError> {
+        if self.fire_service.lock().await.is_some() {
+            return Err(DispatchError::FireRecallActive);
+        }
+
+        self.record_call(&request).await;
+        self.detect_traffic().await;
+
+        let request_id = request.id.clone();
+        self.requests.lock().await.insert(request_id.clone(), HallCall {
+            request_id: request_id.clone(),
+            from: self.building.lowest_floor + request.from as i32,
+            to: request.to.map(|to| self.building.lowest_floor + to as i32),
+            direction: request.direction,
+            car: None,
+            eta_secs: None,
+            status: RequestStatus::Queued,
+            updated_secs: self.clock.now().as_secs_f64(),
+        });
+
+        /* Let the strategy pick from the whole fleet, otherwise wait for a car */
+        match self.dispatch(request.clone()).await {
+            Ok(_) => {}
+            Err(DispatchError::NoCarAvailable) => {
+                log!("ran out of elevators! request {} queued", request_id);
+                self.update_request(&request_id, RequestStatus::Queued).await;
+                self.pending.lock().await.push_back(request);
+            }
+            Err(e) => {
+                self.requests.lock().await.remove(&request_id);
+                return Err(e);
+            }
+        }
+
+        self.hall_call(&request_id).await
+    }
+
+    /* hand the request to the car the strategy picks */
+    async fn dispatch(&self, request: ElevatorRequest) -> Result<usize, DispatchError> {
+        let mode = self.traffic.lock().await.mode;
+        let mut fleet = mode.eligible(&self.fleet_snapshot().await, &request, self.building.entrance_floor());
+
+        /* a car removed since the snapshot was taken is left out and the strategy picks again */
+        let (id, tx) = loop {
+            let id = self.dispatch_strategy.select_elevator(&fleet, &request).ok_or(DispatchError::NoCarAvailable)?;
+            match self.transmitter(id).await {
+                Ok(tx) => break (id, tx),
+                /* a car from outside the snapshot would be picked forever */
+                Err(e) if !fleet.elevators.iter().any(|e| e.id == id) => return Err(e),
+                Err(e) => {
+                    log!("elevator {} picked for request {} but {}, picking again", id, request.id, e);
+                    fleet.retain(|e| e.id != id);
+                }
+            }
+        };
+
+        /* whichever strategy picked the car, the caller learns how long it takes */
+        let eta = fleet
+            .elevators
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| eta(e, &fleet.plans.get(&id).cloned().unwrap_or_default(), request.from, request.direction));
+
+        /* the elevator is taken, whichever pool it was waiting in */
+        self.take_elevator(id).await;
+
+        let request_id = request.id.clone();
+        tx.send(ElevatorSignal::Request(request)).map_err(|_| DispatchError::ChannelClosed(id))?;
+
+        if let Some(hall_call) = self.requests.lock().await.get_mut(&request_id) {
+            hall_call.eta_secs = eta.map(|eta| eta.as_secs_f64());
+        }
+
+        self.update_request(&request_id, RequestStatus::Assigned(id)).await;
+        Ok(id)
+    }
+
+    /* try every queued call once, the ones still without a car keep their place */
+    async fn drain_pending(&self) {
+        let mut pending = self.pending.lock().await;
+        let mut still_pending = VecDeque::new();
+
+        while let Some(request) = pending.pop_front() {
+            match self.dispatch(request.clone()).await {
+                Ok(id) => log!("pending request {} assigned to {}", request.id, id),
+                Err(_) => still_pending.push_back(request),
+            }
+        }
+
+        *pending = still_pending;
+    }
+
+    /* the stops and passengers every car still owes its open hall calls */
+    async fn car_plans(&self) -> HashMap<usize, CarPlan> {
+        let mut plans: HashMap<usize, CarPlan> = HashMap::new();
+        let floor_index = |floor: i32| self.building.floor_index(floor);
+
+        for hall_call in self.requests.lock().await.values() {
+            let (elevator_id, waiting) = match hall_call.status {
+                RequestStatus::Assigned(id) | RequestStatus::CarArriving(id) => (id, true),
+                /* a boarded hall button call is done, its car call is not a hall call */
+                RequestStatus::Boarded(id) if hall_call.to.is_some() => (id, false),
+                _ => continue,
+            };
+
+            let plan = plans.entry(elevator_id).or_default();
+            plan.passengers += 1;
+            if waiting {
+                plan.stops.extend(floor_index(hall_call.from));
+            }
+            plan.stops.extend(hall_call.to.and_then(floor_index));
+        }
+
+        for (elevator_id, car_calls) in self.car_calls.lock().await.iter().filter(|(_, floors)| !floors.is_empty()) {
+            plans.entry(*elevator_id).or_default().stops.extend(car_calls);
+        }
+
+        plans
+    }
+
+    async fn is_idle(&self, elevator_id: usize) -> bool {
+        self.idle_elevators.lock().await.list_elevators().await.iter().any(|e| e.id == elevator_id)
+    }
+
+    async fn record_call(&self, request: &ElevatorRequest) {
+        let now = self.clock.now();
+        let mut history = self.call_history.lock().await;
+
+        history.push_back(CallRecord { at: now, from: request.from, to: request.to, direction: request.direction });
+        while history.front().is_some_and(|record| now - record.at > CALL_HISTORY) {
+            history.pop_front();
+        }
+    }
+
+    /* send an idle car to the nearest home floor no other idle car holds */
+    async fn park(&self, elevator_id: usize, current_floor: usize) {
+        let policy = self.traffic.lock().await.mode.parking(*self.parking.lock().await);
+        let cars = self.fleet.lock().await.len() - self.out_of_service.lock().await.len();
+        let homes = policy.home_floors(&self.building, cars, &*self.call_history.lock().await);
+
+        let mut parked = self.parked.lock().await;
+        if parked.get(&elevator_id) == Some(&current_floor) {
+            return;
+        }
+
+        let mut free = homes;
+        for home in parked.iter().filter(|(id, _)| **id != elevator_id).map(|(_, home)| home) {
+            if let Some(index) = free.iter().position(|floor| floor == home) {
+                free.swap_remove(index);
+            }
+        }
+
+        let Some(home) = free.into_iter().min_by_key(|floor| (floor.abs_diff(current_floor), *floor)) else {
+            parked.remove(&elevator_id);
+            return;
+        };
+
+        parked.insert(elevator_id, home);
+        drop(parked);
+
+        if home != current_floor && let Ok(tx) = self.transmitter(elevator_id).await {
+            log!("Elevator {} parking at {}", elevator_id, home);
+            let _ = tx.send(ElevatorSignal::Park(home));
+        }
+    }
+
+    pub async fn parking_policy(&self) -> ParkingPolicy {
+        *self.parking.lock().await
+    }
+
+    /* idle cars move to the new home floors right away */
+    pub async fn set_parking_policy(&self, policy: ParkingPolicy) {
+        *self.parking.lock().await = policy;
+        self.repark().await;
+    }
+
+    pub async fn traffic(&self) -> TrafficReport {
+        *self.traffic.lock().await
+    }
+
+    pub fn subscribe_traffic(&self) -> Receiver<TrafficReport> {
+        self.traffic_tx.subscribe()
+    }
+
+    /* calls age out of the window even when no new ones come in */
+    async fn watch_traffic(&self) {
+        loop {
+            self.clock.sleep(TRAFFIC_EVERY).await;
+            self.detect_traffic().await;
+        }
+    }
+
+    /* a new mode is announced, and may send idle cars elsewhere */
+    async fn detect_traffic(&self) {
+        let mut report = traffic::classify(&*self.call_history.lock().await, self.building.entrance_floor(), self.clock.now());
+        if let Some(mode) = self.scheduled.lock().await.traffic {
+            report.mode = mode;
+        }
+
+        let mut traffic = self.traffic.lock().await;
+        if report.mode == traffic.mode {
+            *traffic = TrafficReport { since_secs: traffic.since_secs, ..report };
+            return;
+        }
+
+        let configured = *self.parking.lock().await;
+        let reparks = traffic.mode.parking(configured) != report.mode.parking(configured);
+        *traffic = report;
+        drop(traffic);
+
+        log!("TRAFFIC {:?}: {} calls, {:.0}% from and {:.0}% to the entrance", report.mode, report.calls, report.from_entrance * 100.0, report.to_entrance * 100.0);
+        let _ = self.traffic_tx.send(report);
+
+        if reparks {
+            self.repark().await;
+        }
+    }
+
+    pub async fn schedule(&self) -> (Schedule, TimeOfDay, ScheduledModes) {
+        let schedule = self.schedule.lock().await.clone();
+        let time = schedule.time_of_day(self.clock.as_ref());
+        (schedule, time, *self.scheduled.lock().await)
+    }
+
+    /* the new rules apply right away */
+    pub async fn set_schedule(&self, schedule: Schedule) -> Result<(), String> {
+        schedule.validate()?;
+        *self.schedule.lock().await = schedule;
+        self.apply_schedule().await;
+        Ok(())
+    }
+
+    async fn run_schedule(&self) {
+        loop {
+            self.apply_schedule().await;
+            self.clock.sleep(SCHEDULE_EVERY).await;
+        }
+    }
+
+    /* modes are switched when a rule starts or ends, the number of cars is checked every time */
+    async fn apply_schedule(&self) {
+        let (time, modes) = {
+            let schedule = self.schedule.lock().await;
+            let time = schedule.time_of_day(self.clock.as_ref());
+            (time, schedule.active(time))
+        };
+
+        let previous = std::mem::replace(&mut *self.scheduled.lock().await, modes);
+        if modes != previous {
+            log!("SCHEDULE {}: traffic {:?}, cars {:?}, parking {:?}", time, modes.traffic, modes.cars, modes.parking);
+        }
+
+        if modes.traffic != previous.traffic {
+            self.detect_traffic().await;
+        }
+
+        if modes.parking != previous.parking {
+            self.set_parking_policy(modes.parking.unwrap_or(self.building.parking)).await;
+        }
+
+        self.limit_cars(modes.cars).await;
+    }
+
+    /* take idle cars out of service beyond the limit, highest first, put the schedule's own cars back below it */
+    async fn limit_cars(&self, limit: Option<usize>) {
+        /* the recall decides what every car does */
+        if self.fire_service.lock().await.is_some() {
+            return;
+        }
+
+        let out_of_service = self.out_of_service.lock().await.clone();
+        let mut in_service = Vec::new();
+        for elevator_id in self.fleet_layout().await.elevators {
+            if !out_of_service.contains(&elevator_id) && !self.is_faulted(elevator_id).await {
+                in_service.push(elevator_id);
+            }
+        }
+
+        let limit = limit.unwrap_or(usize::MAX);
+        if in_service.len() > limit {
+            /* idle and parked cars go first, a busy car finishes its trip and is checked again next time */
+            let surplus = in_service.len() - limit;
+            let idle: Vec<usize> = self.idle_elevators.lock().await.list_elevators().await.iter().map(|e| e.id).collect();
+            for elevator_id in in_service.into_iter().rev().filter(|id| idle.contains(id)).take(surplus) {
+                if self.take_out_of_service(elevator_id, None).await.is_ok() {
+                    log!("SCHEDULE elevator {} out of service", elevator_id);
+                    self.scheduled_off.lock().await.insert(elevator_id);
+                }
+            }
+            return;
+        }
+
+        let mut back: Vec<usize> = self.scheduled_off.lock().await.iter().copied().collect();
+        back.sort();
+        for elevator_id in back.into_iter().take(limit - in_service.len()) {
+            log!("SCHEDULE elevator {} back in service", elevator_id);
+            let _ = self.return_to_service(elevator_id).await;
+        }
+    }
+
+    /* forget every home floor, idle cars pick them again */
+    async fn repark(&self) {
+        self.parked.lock().await.clear();
+
+        let idle = self.idle_elevators.lock().await.list_elevators().await;
+        for elevator in idle {
+            self.park(elevator.id, elevator.current_floor).await;
+        }
+    }
+
+    async fn is_faulted(&self, elevator_id: usize) -> bool {
+        self.health.lock().await.get(&elevator_id).is_some_and(|health| health.faulted)
+    }
+
+    /* full cars and cars out of service are left out, they take no new hall calls */
+    async fn fleet_snapshot(&self) -> FleetSnapshot {
+        let mut out_of_service = self.out_of_service.lock().await.clone();
+        out_of_service.extend(self.health.lock().await.values().filter(|h| h.faulted).map(|h| h.elevator_id));
+        let mut elevators: Vec<ElevatorState> = self
+            .fleet
+            .lock()
+            .await
+            .values()
+            .filter(|e| e.has_room() && !out_of_service.contains(&e.id))
+            .cloned()
+            .collect();
+        elevators.sort_by_key(|e| e.id);
+
+        let has_room = |id: &usize| elevators.iter().any(|e| e.id == *id);
+
+        FleetSnapshot {
+            plans: self.car_plans().await,
+            next_idle: self.idle_elevators.lock().await.peek_elevator().await.map(|e| e.id).filter(has_room),
+            next_moving_up: self.moving_up_elevators.lock().await.peek_elevator().await.map(|e| e.id).filter(has_room),
+            next_moving_down: self.moving_down_elevators.lock().await.peek_elevator().await.map(|e| e.id).filter(has_room),
+            elevators,
+        }
     }
 }
 
 impl CentralElevatorControllerI for CentralElevatorController {
     async fn print_states(&self) {
-        println!("idle: {:?}", self.idle_elevators.lock().await.elevators.lock().await);
-        println!("up: {:?}", self.moving_up_elevators.lock().await.elevators.lock().await);
-        println!("down: {:?}", self.moving_down_elevators.lock().await.elevators.lock().await);
-    }
-
-    async fn call_for_an_elevator(&self, floor: usize, destination: usize) -> Result<usize, Error> {
-        let _ = self.permits.lock().await.acquire().await;
-        let mut elevator: Option<ElevatorState> = None;
-
-        let mut direction = "up".to_string();
-        if floor > destination {
-            direction = "down".to_string();
-        }
-
-        /* Check if any idle elevator available */
-        let mut idle_elevators = self.idle_elevators.lock().await;
-        let idles = idle_elevators.len().await;
-
-        if idles > 0 {
-            /* Choose idle elevator */
-            elevator = idle_elevators.get_elevator().await;
-            drop(idle_elevators);
-        } else if direction == "up".to_string() {
-            /* Choose elevator that is moving up */
-            let mut moving_up_elevators = self.moving_up_elevators.lock().await;
-            elevator = moving_up_elevators.get_elevator().await;
-        } else {
-            /* Choose elevator that is moving down */
-            let mut moving_down_elevators = self.moving_down_elevators.lock().await;
-            elevator = moving_down_elevators.get_elevator().await;
-        }
-
-        match elevator.clone() {
-            Some(e) => {
-                /* send request to elevator */
-                let signal_transmitter = self.signal_transmitter.get(&e.id);
-                match signal_transmitter {
-                    Some(tx) => {
-                        let _ = tx.send(ElevatorRequest{
-                            from: floor,
-                            to: destination,
-                        });
-                        return Ok(e.id);
-                    }
-                    None => {
-                        return Ok(123 as usize);
-                    }
-                }
-            }
-            None => {
-                println!("ran out of elevators!");
+        let idle_elevators = self.idle_elevators.lock().await;
+        log!("idle ({}): {:?}", idle_elevators.len().await, idle_elevators.list_elevators().await);
+        drop(idle_elevators);
+
+        let moving_up_elevators = self.moving_up_elevators.lock().await;
+        log!("up ({}): {:?}", moving_up_elevators.len().await, moving_up_elevators.list_elevators().await);
+        drop(moving_up_elevators);
+
+        let moving_down_elevators = self.moving_down_elevators.lock().await;
+        log!("down ({}): {:?}", moving_down_elevators.len().await, moving_down_elevators.list_elevators().await);
+    }
+
+    async fn call_for_an_elevator(&self, floor: usize, destination: usize) -> Result<HallCall, DispatchError> {
+        for floor in [floor, destination] {
+            if !self.building.contains(floor) {
+                return Err(DispatchError::FloorOutOfRange(self.building.lowest_floor + floor as i32));
             }
         }
 
-        Ok(123 as usize)
+        self.register_call(ElevatorRequest {
+            id: Uuid::new_v4().to_string(),
+            from: floor,
+            to: Some(destination),
+            direction: if floor > destination { Direction::Down } else { Direction::Up },
+        }).await
     }
 }
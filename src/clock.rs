@@ -0,0 +1,247 @@
+// Every wait in the system goes through a Clock
+// RealClock sleeps for real, AcceleratedClock runs time faster by a fixed factor,
+// VirtualClock only moves when it is advanced, so an hour of traffic can be simulated in milliseconds
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, Timelike};
+use futures::future::BoxFuture;
+use tokio::{sync::oneshot, time::Instant};
+
+use crate::interfaces::Clock;
+
+#[derive(Debug)]
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        RealClock {
+            start: Instant::now(),
+        }
+    }
+
+    pub fn shared() -> Arc<dyn Clock> {
+        Arc::new(RealClock::new())
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn time_of_day(&self) -> Option<Duration> {
+        Some(since_local_midnight(Local::now()))
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/* `factor` seconds of elevator time pass for every real second */
+#[derive(Debug)]
+pub struct AcceleratedClock {
+    start: Instant,
+    started_at: DateTime<Local>, // wall-clock time the elevator day starts from
+    factor: u32,
+}
+
+impl AcceleratedClock {
+    pub fn new(factor: u32) -> Self {
+        AcceleratedClock {
+            start: Instant::now(),
+            started_at: Local::now(),
+            factor: factor.max(1),
+        }
+    }
+}
+
+impl Clock for AcceleratedClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed() * self.factor
+    }
+
+    /* the day runs faster from the local time the clock was started at */
+    fn time_of_day(&self) -> Option<Duration> {
+        Some(since_local_midnight(self.started_at) + self.now())
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration / self.factor))
+    }
+}
+
+/* the local time zone comes from TZ or the system settings */
+fn since_local_midnight(at: DateTime<Local>) -> Duration {
+    let time = at.time();
+    Duration::new(time.num_seconds_from_midnight() as u64, time.nanosecond() % 1_000_000_000)
+}
+
+#[derive(Debug, Default)]
+struct VirtualTime {
+    now: Duration,
+    next_sleeper: u64,
+    sleepers: BTreeMap<(Duration, u64), oneshot::Sender<()>>, // (wake up time, arrival order) -> waker
+}
+
+/* Time stands still until advance() or advance_to_next_wakeup() is called */
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    time: Arc<Mutex<VirtualTime>>,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock::default()
+    }
+
+    /* move time forward, waking every sleeper due on the way in order */
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time.lock().unwrap();
+        let time = &mut *time;
+        let until = time.now + duration;
+
+        while let Some(entry) = time.sleepers.first_entry() {
+            let (wake_up, _) = *entry.key();
+            if wake_up > until {
+                break;
+            }
+
+            time.now = wake_up;
+            let _ = entry.remove().send(());
+        }
+
+        time.now = until;
+    }
+
+    /* jump straight to the earliest sleeper, returns the new time or None when nobody is sleeping */
+    pub fn advance_to_next_wakeup(&self) -> Option<Duration> {
+        let wake_up = {
+            let time = self.time.lock().unwrap();
+            let (wake_up, _) = *time.sleepers.keys().next()?;
+            wake_up - time.now
+        };
+
+        self.advance(wake_up);
+        Some(self.now())
+    }
+
+    /* when the earliest sleeper wakes up */
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        let time = self.time.lock().unwrap();
+        time.sleepers.keys().next().map(|(wake_up, _)| *wake_up)
+    }
+
+    pub fn pending_sleepers(&self) -> usize {
+        self.time.lock().unwrap().sleepers.len()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.time.lock().unwrap().now
+    }
+
+    fn time_of_day(&self) -> Option<Duration> {
+        None
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let (tx, rx) = oneshot::channel();
+
+        {
+            let mut time = self.time.lock().unwrap();
+            let wake_up = time.now + duration;
+            let order = time.next_sleeper;
+            time.next_sleeper += 1;
+            time.sleepers.insert((wake_up, order), tx);
+        }
+
+        Box::pin(async move {
+            /* a dropped clock wakes everyone up */
+            let _ = rx.await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    async fn settle() {
+        for _ in 0..8 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test]
+    fn sleep_waits_until_the_clock_is_advanced_past_it() {
+        let clock = VirtualClock::new();
+        let mut sleep = clock.sleep(10 * SECOND);
+        assert_eq!(clock.next_wakeup(), Some(10 * SECOND));
+
+        clock.advance(9 * SECOND);
+        assert!((&mut sleep).now_or_never().is_none());
+        assert_eq!(clock.pending_sleepers(), 1);
+
+        clock.advance(SECOND);
+        assert!(sleep.now_or_never().is_some());
+        assert_eq!(clock.now(), 10 * SECOND);
+        assert_eq!(clock.pending_sleepers(), 0);
+    }
+
+    #[test]
+    fn advance_to_next_wakeup_jumps_to_the_earliest_sleeper() {
+        let clock = VirtualClock::new();
+        let mut late = clock.sleep(20 * SECOND);
+        let early = clock.sleep(5 * SECOND);
+
+        assert_eq!(clock.advance_to_next_wakeup(), Some(5 * SECOND));
+        assert!(early.now_or_never().is_some());
+        assert!((&mut late).now_or_never().is_none());
+
+        assert_eq!(clock.advance_to_next_wakeup(), Some(20 * SECOND));
+        assert!(late.now_or_never().is_some());
+        assert_eq!(clock.advance_to_next_wakeup(), None);
+    }
+
+    #[tokio::test]
+    async fn advance_wakes_sleepers_by_wake_up_time_then_arrival() {
+        let clock = VirtualClock::new();
+        let woken = Arc::new(Mutex::new(Vec::new()));
+
+        for (name, secs) in [("third", 30), ("first", 10), ("second", 20), ("first again", 10), ("never", 90)] {
+            let sleep = clock.sleep(secs * SECOND);
+            let woken = woken.clone();
+            tokio::spawn(async move {
+                sleep.await;
+                woken.lock().unwrap().push(name);
+            });
+        }
+
+        /* every task waits on its sleep before the clock moves, so they run in the order they are woken */
+        settle().await;
+        clock.advance(60 * SECOND);
+        settle().await;
+
+        assert_eq!(*woken.lock().unwrap(), vec!["first", "first again", "second", "third"]);
+        assert_eq!(clock.now(), 60 * SECOND);
+        assert_eq!(clock.next_wakeup(), Some(90 * SECOND));
+    }
+}
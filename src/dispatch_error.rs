@@ -0,0 +1,69 @@
+// Everything that can go wrong between a hall call and the car serving it
+// Shared by the central controller, the elevator controllers and the pools, the HTTP layer maps each one to a status code
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchError {
+    NoCarAvailable,          /* every car is full, busy or out of service */
+    FloorOutOfRange(i32),    /* building floor number the building does not have */
+    UnknownCar(usize),       /* no car with this id, never added or decommissioned */
+    CarUnreachable(usize),   /* the car is known but nothing listens to its signal channel */
+    ChannelClosed(usize),    /* the car stopped listening to its signal channel */
+    CarMissing(usize),       /* a pool lost track of this car */
+    UnknownRequest(String),  /* no hall call with this id */
+    RequestFinished(String), /* the hall call already arrived or was cancelled */
+    FireRecallActive,        /* hall calls are refused while the cars are recalled */
+    NoFireRecall,            /* firefighter commands need an active recall */
+    PhaseTwoInUse(usize),    /* another car is already under firefighter control */
+    NoHallButton(i32),       /* up on the top floor or down on the bottom floor */
+    CarOutOfService(usize),  /* car calls are refused while the car is out of service */
+    CarFaulted(usize),       /* the supervisor took the car out of dispatch */
+    LastCar(usize),          /* decommissioning it would leave the building without a car */
+}
+
+impl DispatchError {
+    /* stable name for API clients, the message may change */
+    pub fn code(&self) -> &'static str {
+        match self {
+            DispatchError::NoCarAvailable => "no_car_available",
+            DispatchError::FloorOutOfRange(_) => "floor_out_of_range",
+            DispatchError::UnknownCar(_) => "unknown_car",
+            DispatchError::CarUnreachable(_) => "car_unreachable",
+            DispatchError::ChannelClosed(_) => "channel_closed",
+            DispatchError::CarMissing(_) => "car_missing",
+            DispatchError::UnknownRequest(_) => "unknown_request",
+            DispatchError::RequestFinished(_) => "request_finished",
+            DispatchError::FireRecallActive => "fire_recall_active",
+            DispatchError::NoFireRecall => "no_fire_recall",
+            DispatchError::PhaseTwoInUse(_) => "phase_two_in_use",
+            DispatchError::NoHallButton(_) => "no_hall_button",
+            DispatchError::CarOutOfService(_) => "car_out_of_service",
+            DispatchError::CarFaulted(_) => "car_faulted",
+            DispatchError::LastCar(_) => "last_car",
+        }
+    }
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::NoCarAvailable => write!(f, "no elevator is available"),
+            DispatchError::FloorOutOfRange(floor) => write!(f, "floor {} is not in the building", floor),
+            DispatchError::UnknownCar(id) => write!(f, "unknown elevator {}", id),
+            DispatchError::CarUnreachable(id) => write!(f, "elevator {} cannot be reached", id),
+            DispatchError::ChannelClosed(id) => write!(f, "elevator {} stopped listening", id),
+            DispatchError::CarMissing(id) => write!(f, "elevator {} is missing from its pool", id),
+            DispatchError::UnknownRequest(id) => write!(f, "unknown request {}", id),
+            DispatchError::RequestFinished(id) => write!(f, "request {} is already finished", id),
+            DispatchError::FireRecallActive => write!(f, "elevators are recalled for fire service"),
+            DispatchError::NoFireRecall => write!(f, "no fire recall is active"),
+            DispatchError::PhaseTwoInUse(id) => write!(f, "elevator {} is already under firefighter control", id),
+            DispatchError::NoHallButton(floor) => write!(f, "floor {} has no hall button that way", floor),
+            DispatchError::CarOutOfService(id) => write!(f, "elevator {} is out of service", id),
+            DispatchError::CarFaulted(id) => write!(f, "elevator {} is faulted", id),
+            DispatchError::LastCar(id) => write!(f, "elevator {} is the last car in service", id),
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
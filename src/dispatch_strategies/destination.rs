@@ -0,0 +1,138 @@
+// Destination dispatch, every passenger tells where they go before a car is picked
+// Passengers going to the same or nearby floors share a car, so each trip makes fewer stops
+// The cost of a car is how long it takes to reach the caller plus the stops the passenger adds for everyone on board
+// Against any idle car during lobby up-peak the journeys and the longest waits get shorter, the average wait gets a little longer
+use super::{CarPlan, FleetSnapshot, distance};
+use crate::{central_elevator_controller::ElevatorRequest, elevator::{Direction, ElevatorState}, interfaces::DispatchStrategy};
+
+const FLOOR_COST: usize = 15; /* tenths of a second to travel one floor */
+const STOP_COST: usize = 70;  /* tenths of a second per stop, doors opening, dwelling and closing */
+
+#[derive(Debug)]
+pub struct DestinationDispatch;
+
+impl DestinationDispatch {
+    /* floors the car travels before it reaches the caller, and the stops it makes on the way */
+    fn pickup(elevator: &ElevatorState, plan: &CarPlan, request: &ElevatorRequest) -> (usize, usize) {
+        let floor = request.from;
+        let current = elevator.current_floor;
+
+        let heading_there = match elevator.direction {
+            Direction::Up => request.direction == Direction::Up && current <= floor,
+            Direction::Down => request.direction == Direction::Down && current >= floor,
+            _ => true,
+        };
+
+        if heading_there {
+            let on_the_way = plan.stops.iter().filter(|stop| **stop != floor && stop.abs_diff(current) < distance(elevator, floor) && stop.abs_diff(floor) < distance(elevator, floor)).count();
+            return (distance(elevator, floor), on_the_way);
+        }
+
+        /* the car finishes its trip first, then comes back */
+        let turn = match elevator.direction {
+            Direction::Up => plan.stops.last().copied().unwrap_or(current).max(current),
+            _ => plan.stops.first().copied().unwrap_or(current).min(current),
+        };
+
+        (turn.abs_diff(current) + turn.abs_diff(floor), plan.stops.len())
+    }
+
+    fn cost(elevator: &ElevatorState, plan: &CarPlan, request: &ElevatorRequest) -> Option<usize> {
+        /* the group is complete, more passengers would not fit once everyone boards */
+        if plan.passengers >= elevator.capacity.persons {
+            return None;
+        }
+
+        let added_stops = [Some(request.from), request.to].into_iter().flatten().filter(|floor| !plan.stops.contains(floor)).count();
+        let (floors, stops) = Self::pickup(elevator, plan, request);
+
+        Some(floors * FLOOR_COST + stops * STOP_COST + added_stops * STOP_COST * (plan.passengers + 1))
+    }
+}
+
+impl DispatchStrategy for DestinationDispatch {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize> {
+        let no_plan = CarPlan::default();
+
+        fleet
+            .elevators
+            .iter()
+            .filter_map(|e| {
+                let plan = fleet.plans.get(&e.id).unwrap_or(&no_plan);
+                Self::cost(e, plan, request).map(|cost| (cost, e.id))
+            })
+            .min()
+            .map(|(_, id)| id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use super::*;
+    use crate::elevator::Capacity;
+
+    fn car(id: usize, current_floor: usize, direction: Direction) -> ElevatorState {
+        ElevatorState { current_floor, direction, is_moving: direction != Direction::Idle, ..ElevatorState::new(id, Capacity::default()) }
+    }
+
+    fn plan(stops: &[usize], passengers: usize) -> CarPlan {
+        CarPlan { stops: stops.iter().copied().collect::<BTreeSet<usize>>(), passengers }
+    }
+
+    fn request(from: usize, to: usize) -> ElevatorRequest {
+        let direction = if to > from { Direction::Up } else { Direction::Down };
+        ElevatorRequest { id: "test".to_string(), from, to: Some(to), direction }
+    }
+
+    #[test]
+    fn an_idle_car_goes_straight_to_the_caller() {
+        assert_eq!(DestinationDispatch::pickup(&car(0, 2, Direction::Idle), &plan(&[], 0), &request(6, 9)), (4, 0));
+    }
+
+    #[test]
+    fn a_car_heading_to_the_caller_counts_the_stops_before_it() {
+        /* 4 lies between the car and the caller, 8 beyond it */
+        let pickup = DestinationDispatch::pickup(&car(0, 2, Direction::Up), &plan(&[4, 8], 1), &request(6, 9));
+        assert_eq!(pickup, (4, 1));
+    }
+
+    #[test]
+    fn a_car_going_the_other_way_finishes_its_trip_first() {
+        /* up to 9, then down to 3, past every planned stop */
+        let pickup = DestinationDispatch::pickup(&car(0, 5, Direction::Up), &plan(&[7, 9], 2), &request(3, 0));
+        assert_eq!(pickup, (10, 2));
+
+        /* down to 1, then up to 7 */
+        let pickup = DestinationDispatch::pickup(&car(0, 5, Direction::Down), &plan(&[1], 1), &request(7, 9));
+        assert_eq!(pickup, (10, 1));
+    }
+
+    #[test]
+    fn new_stops_cost_every_passenger_on_board() {
+        /* 2 floors to the caller, 4 is a new stop for the three passengers and the caller, 5 is planned already */
+        let cost = DestinationDispatch::cost(&car(0, 2, Direction::Idle), &plan(&[2, 5], 3), &request(4, 5));
+        assert_eq!(cost, Some(2 * FLOOR_COST + STOP_COST * 4));
+    }
+
+    #[test]
+    fn a_complete_group_takes_nobody_else() {
+        let persons = Capacity::default().persons;
+        assert_eq!(DestinationDispatch::cost(&car(0, 0, Direction::Idle), &plan(&[0, 7], persons), &request(0, 7)), None);
+    }
+
+    #[test]
+    fn passengers_for_the_same_floor_share_a_car() {
+        let fleet = FleetSnapshot {
+            elevators: vec![car(0, 0, Direction::Idle), car(1, 0, Direction::Idle)],
+            next_idle: Some(0),
+            next_moving_up: None,
+            next_moving_down: None,
+            plans: HashMap::from([(1, plan(&[0, 7], 2))]),
+        };
+
+        assert_eq!(DestinationDispatch.select_elevator(&fleet, &request(0, 7)), Some(1));
+        assert_eq!(DestinationDispatch.select_elevator(&fleet, &request(0, 3)), Some(0));
+    }
+}
@@ -0,0 +1,19 @@
+// Send the car that gets to the caller first
+// The estimate follows the car's planned route, so a car passing by on its way beats an idle car further away
+use super::{CarPlan, FleetSnapshot, eta};
+use crate::{central_elevator_controller::ElevatorRequest, interfaces::DispatchStrategy};
+
+#[derive(Debug)]
+pub struct LowestEta;
+
+impl DispatchStrategy for LowestEta {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize> {
+        let no_plan = CarPlan::default();
+
+        fleet
+            .elevators
+            .iter()
+            .min_by_key(|e| (eta(e, fleet.plans.get(&e.id).unwrap_or(&no_plan), request.from, request.direction), e.id))
+            .map(|e| e.id)
+    }
+}
@@ -0,0 +1,16 @@
+// Send the car carrying the fewest passengers, the closest one wins a tie
+use super::{FleetSnapshot, distance};
+use crate::{central_elevator_controller::ElevatorRequest, interfaces::DispatchStrategy};
+
+#[derive(Debug)]
+pub struct LeastLoaded;
+
+impl DispatchStrategy for LeastLoaded {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize> {
+        fleet
+            .elevators
+            .iter()
+            .min_by_key(|e| (e.current_load, distance(e, request.from), e.id))
+            .map(|e| e.id)
+    }
+}
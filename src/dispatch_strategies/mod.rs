@@ -0,0 +1,185 @@
+pub mod destination;
+pub mod eta;
+pub mod least_loaded;
+pub mod nearest_car;
+pub mod pool_order;
+pub mod round_robin;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    door::DOOR_CYCLE,
+    elevator::{Direction, ElevatorState},
+    elevator_controller::FLOOR_TRAVEL,
+    interfaces::DispatchStrategy,
+};
+use destination::DestinationDispatch;
+use eta::LowestEta;
+use least_loaded::LeastLoaded;
+use nearest_car::NearestCar;
+use pool_order::PoolOrder;
+use round_robin::RoundRobin;
+
+/* What a dispatch strategy gets to see when a hall call comes in */
+#[derive(Debug, Clone)]
+pub struct FleetSnapshot {
+    pub elevators: Vec<ElevatorState>, // latest state of every car in service with room, ordered by id
+
+    // the car each pool would hand out next
+    pub next_idle: Option<usize>,
+    pub next_moving_up: Option<usize>,
+    pub next_moving_down: Option<usize>,
+
+    pub plans: HashMap<usize, CarPlan>, // by car id, cars without an open hall call are missing
+}
+
+impl FleetSnapshot {
+    /* leave out the cars `keep` rejects, a pool's next car included */
+    pub fn retain(&mut self, keep: impl Fn(&ElevatorState) -> bool) {
+        self.elevators.retain(|e| keep(e));
+
+        let elevators = &self.elevators;
+        let kept = |id: Option<usize>| id.filter(|id| elevators.iter().any(|e| e.id == *id));
+        self.next_idle = kept(self.next_idle);
+        self.next_moving_up = kept(self.next_moving_up);
+        self.next_moving_down = kept(self.next_moving_down);
+    }
+}
+
+/* What a car was given and has not finished yet, from the hall calls assigned to it */
+#[derive(Debug, Clone, Default)]
+pub struct CarPlan {
+    pub stops: BTreeSet<usize>, // pick up floors of callers not in yet, destinations of everyone, car calls
+    pub passengers: usize,      // callers assigned to the car and not arrived yet, in the car or waiting for it
+}
+
+/* Which strategy the central controller dispatches hall calls with */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DispatchKind {
+    PoolOrder,   /* any idle car, otherwise a car moving the same way */
+    NearestCar,  /* closest car that does not have to turn around */
+    LeastLoaded, /* car with the fewest passengers */
+    RoundRobin,  /* every car in turn */
+    Destination, /* car whose trip the passenger adds the fewest stops to */
+    #[default]
+    LowestEta,   /* car that reaches the caller first along its planned route */
+}
+
+impl DispatchKind {
+    pub fn from_name(name: &str) -> Option<DispatchKind> {
+        match name {
+            "pool-order" => Some(DispatchKind::PoolOrder),
+            "nearest-car" => Some(DispatchKind::NearestCar),
+            "least-loaded" => Some(DispatchKind::LeastLoaded),
+            "round-robin" => Some(DispatchKind::RoundRobin),
+            "destination" => Some(DispatchKind::Destination),
+            "lowest-eta" => Some(DispatchKind::LowestEta),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn DispatchStrategy> {
+        match self {
+            DispatchKind::PoolOrder => Box::new(PoolOrder),
+            DispatchKind::NearestCar => Box::new(NearestCar),
+            DispatchKind::LeastLoaded => Box::new(LeastLoaded),
+            DispatchKind::RoundRobin => Box::new(RoundRobin::new()),
+            DispatchKind::Destination => Box::new(DestinationDispatch),
+            DispatchKind::LowestEta => Box::new(LowestEta),
+        }
+    }
+}
+
+/* floors between the car and the caller */
+pub fn distance(elevator: &ElevatorState, floor: usize) -> usize {
+    elevator.current_floor.abs_diff(floor)
+}
+
+/* how long until the car stops at the floor for a caller going that way */
+/* the car finishes its trip in its direction first (LOOK), every planned stop passed on the way costs a door cycle */
+pub fn eta(elevator: &ElevatorState, plan: &CarPlan, floor: usize, direction: Direction) -> Duration {
+    let current = elevator.current_floor;
+    let highest = plan.stops.last().copied().unwrap_or(current).max(current).max(floor);
+    let lowest = plan.stops.first().copied().unwrap_or(current).min(current).min(floor);
+
+    /* floors where the car turns around before it gets to the caller */
+    let turns: Vec<usize> = match elevator.direction {
+        Direction::Up if direction == Direction::Up && floor >= current => vec![],
+        Direction::Up if direction == Direction::Down => vec![highest],
+        Direction::Up => vec![highest, lowest],
+        Direction::Down if direction == Direction::Down && floor <= current => vec![],
+        Direction::Down if direction == Direction::Up => vec![lowest],
+        Direction::Down => vec![lowest, highest],
+        _ => vec![],
+    };
+
+    let mut floors = 0;
+    let mut passed: BTreeSet<usize> = BTreeSet::new();
+    let mut at = current;
+    for next in turns.into_iter().chain([floor]) {
+        floors += at.abs_diff(next);
+        passed.extend(plan.stops.range(at.min(next)..=at.max(next)).filter(|stop| **stop != floor && **stop != current));
+        at = next;
+    }
+
+    let mut eta = FLOOR_TRAVEL * floors as u32 + DOOR_CYCLE * passed.len() as u32;
+
+    /* the doors have to close before the car leaves */
+    if elevator.is_door_open && floor != current {
+        eta += DOOR_CYCLE;
+    }
+
+    eta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::Capacity;
+
+    fn car(current_floor: usize, direction: Direction) -> ElevatorState {
+        ElevatorState { current_floor, direction, is_moving: direction != Direction::Idle, ..ElevatorState::new(0, Capacity::default()) }
+    }
+
+    fn plan(stops: &[usize]) -> CarPlan {
+        CarPlan { stops: stops.iter().copied().collect(), passengers: stops.len() }
+    }
+
+    #[test]
+    fn an_idle_car_only_travels() {
+        assert_eq!(eta(&car(2, Direction::Idle), &plan(&[]), 5, Direction::Up), FLOOR_TRAVEL * 3);
+        assert_eq!(eta(&car(2, Direction::Idle), &plan(&[]), 2, Direction::Down), Duration::ZERO);
+    }
+
+    #[test]
+    fn a_car_passing_the_caller_stops_on_the_way_first() {
+        /* stops at 4 before the caller at 6, 8 comes after */
+        assert_eq!(eta(&car(2, Direction::Up), &plan(&[4, 8]), 6, Direction::Up), FLOOR_TRAVEL * 4 + DOOR_CYCLE);
+        assert_eq!(eta(&car(8, Direction::Down), &plan(&[1, 5]), 3, Direction::Down), FLOOR_TRAVEL * 5 + DOOR_CYCLE);
+    }
+
+    #[test]
+    fn a_car_going_the_other_way_turns_around_first() {
+        /* up to 9, back down to 3 */
+        assert_eq!(eta(&car(5, Direction::Up), &plan(&[7, 9]), 3, Direction::Down), FLOOR_TRAVEL * 10 + DOOR_CYCLE * 2);
+
+        /* a caller below going up waits for the car to come back up after its lowest stop */
+        assert_eq!(eta(&car(5, Direction::Up), &plan(&[7, 1]), 3, Direction::Up), FLOOR_TRAVEL * 10 + DOOR_CYCLE * 2);
+
+        /* a car going down reaches a caller above going up after its lowest stop */
+        assert_eq!(eta(&car(5, Direction::Down), &plan(&[2]), 7, Direction::Up), FLOOR_TRAVEL * 8 + DOOR_CYCLE);
+    }
+
+    #[test]
+    fn open_doors_close_before_the_car_leaves() {
+        let standing = ElevatorState { is_door_open: true, ..car(2, Direction::Idle) };
+        assert_eq!(eta(&standing, &plan(&[]), 5, Direction::Up), FLOOR_TRAVEL * 3 + DOOR_CYCLE);
+        assert_eq!(eta(&standing, &plan(&[]), 2, Direction::Up), Duration::ZERO);
+    }
+}
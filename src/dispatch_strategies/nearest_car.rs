@@ -0,0 +1,27 @@
+// Send the closest car
+// A car already heading away from the caller has to come back, so it only wins when nothing else is around
+use super::{FleetSnapshot, distance};
+use crate::{central_elevator_controller::ElevatorRequest, elevator::{Direction, ElevatorState}, interfaces::DispatchStrategy};
+
+#[derive(Debug)]
+pub struct NearestCar;
+
+impl NearestCar {
+    fn is_moving_away(elevator: &ElevatorState, floor: usize) -> bool {
+        match elevator.direction {
+            Direction::Up => elevator.current_floor > floor,
+            Direction::Down => elevator.current_floor < floor,
+            _ => false,
+        }
+    }
+}
+
+impl DispatchStrategy for NearestCar {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize> {
+        fleet
+            .elevators
+            .iter()
+            .min_by_key(|e| (Self::is_moving_away(e, request.from), distance(e, request.from), e.id))
+            .map(|e| e.id)
+    }
+}
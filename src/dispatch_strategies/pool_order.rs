@@ -0,0 +1,20 @@
+// The original rule of the central controller
+// Take any idle car, otherwise the next car moving in the caller's direction
+use super::FleetSnapshot;
+use crate::{central_elevator_controller::ElevatorRequest, elevator::Direction, interfaces::DispatchStrategy};
+
+#[derive(Debug)]
+pub struct PoolOrder;
+
+impl DispatchStrategy for PoolOrder {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize> {
+        if fleet.next_idle.is_some() {
+            return fleet.next_idle;
+        }
+
+        match request.direction {
+            Direction::Up => fleet.next_moving_up,
+            _ => fleet.next_moving_down,
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// Hand the calls to every car in turn, regardless of where the cars are
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::FleetSnapshot;
+use crate::{central_elevator_controller::ElevatorRequest, interfaces::DispatchStrategy};
+
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        RoundRobin {
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl DispatchStrategy for RoundRobin {
+    fn select_elevator(&self, fleet: &FleetSnapshot, _request: &ElevatorRequest) -> Option<usize> {
+        if fleet.elevators.is_empty() {
+            return None;
+        }
+
+        let turn = self.next.fetch_add(1, Ordering::Relaxed);
+        Some(fleet.elevators[turn % fleet.elevators.len()].id)
+    }
+}
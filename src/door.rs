@@ -0,0 +1,293 @@
+// Door state machine of a single car
+// Closed -> Opening -> Open -> Closing -> Closed, a closing door hit by an obstruction goes Reopening -> Open
+// After NUDGE_AFTER obstructions at one stop the door stops waiting, closes slowly and no longer reverses (nudging)
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, broadcast::Sender};
+
+use crate::{
+    elevator::ElevatorState,
+    interfaces::{Clock, ElevatorI},
+};
+
+const DOOR_TRAVEL: Duration = Duration::from_secs(1); /* fully open or fully closed */
+const NUDGE_TRAVEL: Duration = Duration::from_secs(3); /* closing slowly with the buzzer on */
+const DWELL: Duration = Duration::from_secs(5); /* how long the door stays open at a stop */
+const NUDGE_AFTER: usize = 3;
+
+/* a stop without anyone holding the door: opening, dwell and closing */
+pub const DOOR_CYCLE: Duration = Duration::from_secs(2 * DOOR_TRAVEL.as_secs() + DWELL.as_secs());
+
+/* Serialized as "closed", "opening", "open", "closing", "reopening" and "nudging" */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoorState {
+    #[default]
+    Closed,
+    Opening,
+    Open,
+    Closing,
+    Reopening, /* was closing, an obstruction or the open button sent it back */
+    Nudging,   /* closing slowly, ignores obstructions */
+}
+
+/* Inputs from inside the cab and the door edge sensor */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoorCommand {
+    Open,        /* door open button, holds an open door and reverses a closing one */
+    Close,       /* door close button, cuts the dwell short */
+    Obstruction, /* something crossed the door edge sensor */
+}
+
+impl DoorCommand {
+    pub fn from_name(name: &str) -> Option<DoorCommand> {
+        match name {
+            "open" => Some(DoorCommand::Open),
+            "close" => Some(DoorCommand::Close),
+            "obstruct" => Some(DoorCommand::Obstruction),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DoorInputs {
+    commands: VecDeque<DoorCommand>,
+    obstructions: usize, /* since the door last opened */
+}
+
+#[derive(Debug, Clone)]
+pub struct DoorOperator {
+    inputs: Arc<Mutex<DoorInputs>>,
+    changed: Arc<Notify>,
+    clock: Arc<dyn Clock>,
+    state_transmitter: Sender<ElevatorState>, /* every transition is published */
+}
+
+impl DoorOperator {
+    pub fn new(clock: Arc<dyn Clock>, state_tx: Sender<ElevatorState>) -> Self {
+        DoorOperator {
+            inputs: Arc::new(Mutex::new(DoorInputs::default())),
+            changed: Arc::new(Notify::new()),
+            clock,
+            state_transmitter: state_tx,
+        }
+    }
+
+    /* picked up by the door cycle running right now, if any */
+    pub fn command(&self, command: DoorCommand) {
+        self.inputs.lock().unwrap().commands.push_back(command);
+        self.changed.notify_one();
+    }
+
+    pub async fn open(&self, elevator: &mut ElevatorState) {
+        if elevator.door == DoorState::Open {
+            return;
+        }
+
+        /* presses made while the door was shut are stale */
+        *self.inputs.lock().unwrap() = DoorInputs::default();
+
+        self.publish(elevator, DoorState::Opening);
+        self.clock.sleep(DOOR_TRAVEL).await;
+        self.publish(elevator, DoorState::Open);
+    }
+
+    /* keep the door open for the dwell, the open button or an obstruction starts it over */
+    pub async fn dwell(&self, elevator: &mut ElevatorState) {
+        while !self.must_nudge() {
+            match self.wait(DWELL).await {
+                None | Some(DoorCommand::Close) => return,
+                Some(DoorCommand::Open) | Some(DoorCommand::Obstruction) => {
+                    /* published again, a held door is not a stalled car */
+                    log!("Elevator {} door held open", elevator.id);
+                    self.publish(elevator, DoorState::Open);
+                }
+            }
+        }
+    }
+
+    /* close the door, reversing on obstructions until the car gives up and nudges */
+    pub async fn close(&self, elevator: &mut ElevatorState) {
+        while elevator.door != DoorState::Closed {
+            if self.must_nudge() {
+                log!("Elevator {} nudging door closed", elevator.id);
+                self.publish(elevator, DoorState::Nudging);
+                self.clock.sleep(NUDGE_TRAVEL).await;
+                self.publish(elevator, DoorState::Closed);
+                break;
+            }
+
+            self.publish(elevator, DoorState::Closing);
+            match self.wait(DOOR_TRAVEL).await {
+                None => self.publish(elevator, DoorState::Closed),
+                Some(DoorCommand::Close) => {
+                    /* already closing, wait for the rest of it */
+                    self.clock.sleep(DOOR_TRAVEL).await;
+                    self.publish(elevator, DoorState::Closed);
+                }
+                Some(DoorCommand::Open) | Some(DoorCommand::Obstruction) => {
+                    self.publish(elevator, DoorState::Reopening);
+                    self.clock.sleep(DOOR_TRAVEL).await;
+                    self.publish(elevator, DoorState::Open);
+                    self.dwell(elevator).await;
+                }
+            }
+        }
+    }
+
+    /* sleep, cut short by the next command, returns the command that woke it up */
+    async fn wait(&self, duration: Duration) -> Option<DoorCommand> {
+        let deadline = self.clock.now() + duration;
+
+        loop {
+            if let Some(command) = self.next_command() {
+                return Some(command);
+            }
+
+            let now = self.clock.now();
+            if now >= deadline {
+                return None;
+            }
+
+            tokio::select! {
+                _ = self.clock.sleep(deadline - now) => {}
+                _ = self.changed.notified() => {}
+            }
+        }
+    }
+
+    fn next_command(&self) -> Option<DoorCommand> {
+        let mut inputs = self.inputs.lock().unwrap();
+        let command = inputs.commands.pop_front()?;
+        if command == DoorCommand::Obstruction {
+            inputs.obstructions += 1;
+        }
+        Some(command)
+    }
+
+    fn must_nudge(&self) -> bool {
+        self.inputs.lock().unwrap().obstructions >= NUDGE_AFTER
+    }
+
+    fn publish(&self, elevator: &mut ElevatorState, door: DoorState) {
+        elevator.set_door(door);
+        let _ = self.state_transmitter.send(elevator.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::{Receiver, channel};
+
+    use super::*;
+    use crate::{clock::VirtualClock, elevator::Capacity};
+
+    fn door() -> (DoorOperator, VirtualClock, Receiver<ElevatorState>) {
+        let clock = VirtualClock::new();
+        let (state_tx, state_rx) = channel(64);
+        (DoorOperator::new(Arc::new(clock.clone()), state_tx), clock, state_rx)
+    }
+
+    /* open, dwell and close at a stop, in the background */
+    fn stop(door: &DoorOperator) -> tokio::task::JoinHandle<ElevatorState> {
+        let door = door.clone();
+        tokio::spawn(async move {
+            let mut elevator = ElevatorState::new(0, Capacity::default());
+            door.open(&mut elevator).await;
+            door.dwell(&mut elevator).await;
+            door.close(&mut elevator).await;
+            elevator
+        })
+    }
+
+    async fn settle() {
+        for _ in 0..8 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn run(clock: &VirtualClock) {
+        settle().await;
+        while clock.advance_to_next_wakeup().is_some() {
+            settle().await;
+        }
+    }
+
+    fn published(state_rx: &mut Receiver<ElevatorState>) -> Vec<DoorState> {
+        let mut doors = Vec::new();
+        while let Ok(state) = state_rx.try_recv() {
+            doors.push(state.door);
+        }
+        doors
+    }
+
+    #[tokio::test]
+    async fn a_stop_opens_dwells_and_closes() {
+        let (door, clock, mut state_rx) = door();
+        let cycle = stop(&door);
+        run(&clock).await;
+
+        let elevator = cycle.await.unwrap();
+        assert_eq!(elevator.door, DoorState::Closed);
+        assert!(!elevator.is_door_open);
+        assert_eq!(published(&mut state_rx), vec![DoorState::Opening, DoorState::Open, DoorState::Closing, DoorState::Closed]);
+        assert_eq!(clock.now(), DOOR_CYCLE);
+    }
+
+    #[tokio::test]
+    async fn an_obstruction_reopens_a_closing_door() {
+        let (door, clock, mut state_rx) = door();
+        let cycle = stop(&door);
+
+        settle().await;
+        clock.advance(DOOR_TRAVEL);
+        settle().await;
+        clock.advance(DWELL);
+        settle().await;
+        assert_eq!(published(&mut state_rx), vec![DoorState::Opening, DoorState::Open, DoorState::Closing]);
+
+        door.command(DoorCommand::Obstruction);
+        run(&clock).await;
+
+        assert_eq!(cycle.await.unwrap().door, DoorState::Closed);
+        assert_eq!(published(&mut state_rx), vec![DoorState::Reopening, DoorState::Open, DoorState::Closing, DoorState::Closed]);
+        /* obstructed as soon as it started closing, it opened again and waited a full dwell */
+        assert_eq!(clock.now(), DOOR_CYCLE + DOOR_TRAVEL + DWELL);
+    }
+
+    #[tokio::test]
+    async fn repeated_obstructions_make_the_door_nudge_closed() {
+        let (door, clock, mut state_rx) = door();
+        let cycle = stop(&door);
+
+        settle().await;
+        clock.advance(DOOR_TRAVEL);
+        settle().await;
+        for _ in 0..NUDGE_AFTER {
+            door.command(DoorCommand::Obstruction);
+        }
+        settle().await;
+
+        /* a nudging door no longer reverses, it closes slowly without a dwell */
+        door.command(DoorCommand::Obstruction);
+        clock.advance(NUDGE_TRAVEL - Duration::from_millis(1));
+        settle().await;
+        assert!(!cycle.is_finished());
+
+        clock.advance(Duration::from_millis(1));
+        settle().await;
+        assert!(cycle.is_finished());
+        assert_eq!(cycle.await.unwrap().door, DoorState::Closed);
+        let mut expected = vec![DoorState::Opening];
+        expected.extend([DoorState::Open; NUDGE_AFTER + 1]);
+        expected.extend([DoorState::Nudging, DoorState::Closed]);
+        assert_eq!(published(&mut state_rx), expected);
+    }
+}
@@ -1,53 +1,108 @@
-use tokio::time::{sleep, Duration};
-use std::fmt::Error;
+use serde::{Deserialize, Serialize};
 
+use crate::{door::DoorState, interfaces::ElevatorI};
 
-use serde::{Deserialize, Serialize};
+/* Serialized as "up", "down", "idle", "stopped" and "out_of_service" */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    #[default]
+    Idle,
+    Stopped,      /* parked with its doors open, not taking calls */
+    OutOfService, /* taken away from dispatch */
+}
+
+/* cars are labelled A, B, C... in the lobby, then AA, AB... after Z, ids are never reused so neither are labels */
+pub fn car_label(id: usize) -> String {
+    let mut label = Vec::new();
+    let mut rest = id + 1;
+    while rest > 0 {
+        rest -= 1;
+        label.push(b'A' + (rest % 26) as u8);
+        rest /= 26;
+    }
 
-use crate::interfaces::ElevatorI;
+    label.iter().rev().map(|letter| *letter as char).collect()
+}
+
+/* used to turn a head count into a weight when a car is limited in kg */
+pub const AVERAGE_PASSENGER_KG: usize = 75;
+
+/* How much a car may carry, the car is full as soon as either limit is reached */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capacity {
+    pub persons: usize,
+    pub kg: Option<usize>,
+}
+
+impl Default for Capacity {
+    fn default() -> Self {
+        Capacity { persons: 8, kg: None }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ElevatorState {
     pub id: usize,
 
     // states
-    pub is_door_open: bool,
+    pub is_door_open: bool, /* anything but Closed */
+    pub door: DoorState,
     pub is_moving: bool,
 
     pub current_floor: usize,
     pub current_load: usize,
+    pub capacity: Capacity,
 
-    pub direction: String,
-    pub initial_direction: String,
+    pub direction: Direction,
+    pub initial_direction: Direction,
 }
 
 impl ElevatorState {
-    pub fn new(id: usize) -> ElevatorState {
-        let elevator = ElevatorState {
-            id: id,
+    pub fn new(id: usize, capacity: Capacity) -> ElevatorState {
+        ElevatorState {
+            id,
             is_door_open: false,
+            door: DoorState::Closed,
             is_moving: false,
             current_floor: 0,
             current_load: 0,
-            direction: "idle".to_string(),
-            initial_direction: "idle".to_string(),
-        };
+            capacity,
+            direction: Direction::Idle,
+            initial_direction: Direction::Idle,
+        }
+    }
 
-        return elevator;
+    /* can one more passenger get in */
+    pub fn has_room(&self) -> bool {
+        let load = self.current_load + 1;
+        load <= self.capacity.persons && self.capacity.kg.is_none_or(|kg| load * AVERAGE_PASSENGER_KG <= kg)
     }
 }
 
 
 impl ElevatorI for ElevatorState {
-     async fn close_door(&mut self) -> Result<(), Error> {
-        sleep(Duration::from_secs(1)).await;
-        self.is_door_open = false;
-        Ok(())
+    fn set_door(&mut self, door: DoorState) {
+        self.door = door;
+        self.is_door_open = door != DoorState::Closed;
     }
-    
-    async fn open_door(&mut self) -> Result<(), Error> {
-        sleep(Duration::from_secs(1)).await;
-        self.is_door_open = true;
-        Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn car_labels_do_not_wrap() {
+        assert_eq!(car_label(0), "A");
+        assert_eq!(car_label(25), "Z");
+        assert_eq!(car_label(26), "AA");
+        assert_eq!(car_label(27), "AB");
+        assert_eq!(car_label(51), "AZ");
+        assert_eq!(car_label(52), "BA");
+        assert_eq!(car_label(26 + 26 * 26), "AAA");
     }
-}
\ No newline at end of file
+}
@@ -1,203 +1,840 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    fmt::Error,
+    collections::BTreeSet,
     sync::Arc,
+    time::Duration,
 };
 
-use tokio::time::{Duration, sleep};
-
 use tokio::sync::Mutex;
-use tokio::sync::broadcast::{Receiver, Sender};
+use tokio::sync::broadcast::{Receiver, Sender, error::RecvError};
 
 use crate::{
-    central_elevator_controller::ElevatorRequest,
-    elevator::ElevatorState,
-    interfaces::{ElevatorControllerI, ElevatorI},
+    central_elevator_controller::{ElevatorEvent, ElevatorRequest, ElevatorSignal, RequestStatus},
+    dispatch_error::DispatchError,
+    door::{DoorCommand, DoorOperator, DoorState},
+    elevator::{Capacity, Direction, ElevatorState},
+    interfaces::{Clock, ElevatorControllerI},
 };
 
+pub const FLOOR_TRAVEL: Duration = Duration::from_millis(1500); /* from one floor to the next */
+pub const HEARTBEAT_EVERY: Duration = Duration::from_secs(5); /* the signal loop tells the central controller it is alive */
+
+/* Whether the car takes calls */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServiceMode {
+    Normal,
+    OutOfService, /* finishing its riders, then parked until put back in service */
+    FireRecall,   /* non-stop to the recall floor, parked there with the doors open */
+    Firefighter,  /* phase II, moves and opens its doors only on firefighter commands */
+}
+
+/* Collective control (LOOK) of a single car */
+/* The car serves every stop ahead of it, then turns around, it never skips a floor it would have to come back to */
+/* The signal loop takes calls while a worker moves the car, the worker holds the car's state for a whole trip */
 #[derive(Debug, Clone)]
 pub struct ElevatorController {
+    id: usize,
     pub state: Arc<Mutex<ElevatorState>>,
-    pub destination_map: Arc<Mutex<HashMap<usize, bool>>>,
-    pub destination_list: Arc<Mutex<VecDeque<usize>>>,
+
+    /* where the car stops, a passenger going up is only picked up by a car going up */
+    pub up_stops: Arc<Mutex<BTreeSet<usize>>>, /* floors to stop at while travelling up */
+    pub down_stops: Arc<Mutex<BTreeSet<usize>>>, /* floors to stop at while travelling down */
+    car_calls: Arc<Mutex<BTreeSet<usize>>>, /* floors pressed inside the car, not served yet, even a full car stops there */
+    heading_home: Arc<Mutex<Option<usize>>>, /* home floor of an idle car on its way there, its only stop, dropped on the next call */
+
+    /* passengers get in when the car opens at their floor going their way, current_load counts the riders */
+    waiting: Arc<Mutex<Vec<ElevatorRequest>>>, /* passengers assigned to this car, not in yet, handed back when the car is full */
+    riding: Arc<Mutex<Vec<ElevatorRequest>>>, /* passengers in the car */
+    leaving: Arc<Mutex<Vec<ElevatorRequest>>>, /* riders whose call was cancelled, they get off at the next stop */
+
     pub state_transmitter: Sender<ElevatorState>, /* used to send state to central controller */
+    event_transmitter: Sender<ElevatorEvent>, /* hand backs and passenger progress, to central controller */
 
-    is_busy: Arc<Mutex<bool>>,
+    /* door buttons reach the operator while the doors move, the open button also opens an idle car */
+    door: DoorOperator,
+    firefighter_door: Arc<Mutex<Option<bool>>>, /* door command from the firefighter, open when true, applied once the car stands */
+    clock: Arc<dyn Clock>,
+
+    is_busy: Arc<Mutex<bool>>, /* a worker is serving the stops */
+    mode: Arc<Mutex<ServiceMode>>, /* out of service and fire recall drop or hand back the calls, see ServiceMode */
 }
 
 impl ElevatorController {
-    pub fn new(id: usize, state_tx: Sender<ElevatorState>) -> Self {
-        return ElevatorController {
-            state: Arc::new(Mutex::new(ElevatorState::new(id))),
-            destination_map: Arc::new(Mutex::new(HashMap::new())),
-            destination_list: Arc::new(Mutex::new(VecDeque::new())),
+    pub fn new(id: usize, capacity: Capacity, state_tx: Sender<ElevatorState>, event_tx: Sender<ElevatorEvent>, clock: Arc<dyn Clock>) -> Self {
+        ElevatorController {
+            id,
+            state: Arc::new(Mutex::new(ElevatorState::new(id, capacity))),
+            up_stops: Arc::new(Mutex::new(BTreeSet::new())),
+            down_stops: Arc::new(Mutex::new(BTreeSet::new())),
+            waiting: Arc::new(Mutex::new(Vec::new())),
+            riding: Arc::new(Mutex::new(Vec::new())),
+            leaving: Arc::new(Mutex::new(Vec::new())),
+            car_calls: Arc::new(Mutex::new(BTreeSet::new())),
+            heading_home: Arc::new(Mutex::new(None)),
+            firefighter_door: Arc::new(Mutex::new(None)),
+            door: DoorOperator::new(clock.clone(), state_tx.clone()),
             state_transmitter: state_tx,
+            event_transmitter: event_tx,
             is_busy: Arc::new(Mutex::new(false)),
-        };
+            mode: Arc::new(Mutex::new(ServiceMode::Normal)),
+            clock,
+        }
     }
 
     /* Receive a channel receiver and listen to each request made by central controller */
-    pub async fn listen_request(&self, signal_receiver: Receiver<ElevatorRequest>) {
+    pub async fn listen_request(&self, signal_receiver: Receiver<ElevatorSignal>) {
         let mut bind = signal_receiver;
+        let mut heartbeat = self.clock.sleep(HEARTBEAT_EVERY);
 
         loop {
-            match bind.recv().await {
-                Ok(request) => {
-                    /* drop request as already in request queue */
-                    let mut destination_list = self.destination_list.lock().await;
-
-                    let mut destination_map = self.destination_map.lock().await;
-                    let in_destination_list = destination_map.get(&request.from);
-                    match in_destination_list {
-                        Some(_) => {
-                            
-                        }
-                        None => {
-                            /* append the request to the queue */
-                            println!("appending to queue : {}", request.from);
-                            destination_list.push_front(request.from);
-                            destination_map.insert(request.from, true);
-                        }
-                    }
+            let signal = tokio::select! {
+                signal = bind.recv() => signal,
+                _ = &mut heartbeat => {
+                    let _ = self.event_transmitter.send(ElevatorEvent::Heartbeat(self.id));
+                    heartbeat = self.clock.sleep(HEARTBEAT_EVERY);
+                    continue;
+                }
+            };
 
-                    /* drop request as already in request queue */
-                    let in_destination_list = destination_map.get(&request.to);
-                    match in_destination_list {
-                        Some(_) => {
-                            
-                        }
-                        None => {
-                            /* append the request to the queue */
-                            println!("appending to queue : {}", request.to);
-                            destination_list.push_front(request.to);
-                            destination_map.insert(request.to, true);
-                        }
-                    }
+            match signal {
+                Ok(ElevatorSignal::Cancel(request_id)) => {
+                    self.cancel(&request_id).await;
+                }
+                Ok(ElevatorSignal::Request(request)) => {
+                    self.accept(request).await;
+                }
+                Ok(ElevatorSignal::OutOfService(evacuate_to)) => {
+                    self.take_out_of_service(evacuate_to).await;
+                }
+                Ok(ElevatorSignal::InService) => {
+                    self.return_to_service().await;
+                }
+                Ok(ElevatorSignal::Recall(floor)) => {
+                    self.recall(floor).await;
+                }
+                Ok(ElevatorSignal::EndRecall) => {
+                    self.end_recall().await;
+                }
+                Ok(ElevatorSignal::FirefighterGoTo(floor)) => {
+                    self.firefighter_go_to(floor).await;
+                }
+                Ok(ElevatorSignal::FirefighterDoor(open)) => {
+                    self.firefighter_door(open).await;
+                }
+                Ok(ElevatorSignal::CarCall(floor, direction)) => {
+                    self.press_car_call(floor, direction).await;
+                }
+                Ok(ElevatorSignal::Door(command)) => {
+                    self.press_door_button(command).await;
+                }
+                Ok(ElevatorSignal::Park(floor)) => {
+                    self.go_home(floor).await;
+                }
+                /* the car was decommissioned */
+                Err(RecvError::Closed) => break,
+                Err(e) => {
+                    log!("error receiving signal {}", e)
+                }
+            }
+        }
+    }
 
-                    /* if busy, quit. will be processed soon */
-                    let mut busy = self.is_busy.lock().await;
-                    if *busy {
-                        continue;
-                    }
+    async fn accept(&self, request: ElevatorRequest) {
+        /* sent before central controller knew, somebody else has to take it */
+        if *self.mode.lock().await != ServiceMode::Normal {
+            self.hand_back(vec![request]);
+            return;
+        }
 
-                    *busy = true;
-                    drop(busy);
-                    drop(destination_list);
-                    drop(destination_map);
-
-                    /* if not busy, process this request */
-                    /* and while there's a queued request (probably by another thread), keep going */
-                    loop {
-                        let mut destination_list = self.destination_list.lock().await;
-                        if destination_list.len() == 0 {
-                            break;
-                        }
-
-                        let next = destination_list.pop_back();
-                        drop(destination_list);
-
-                        match next {
-                            Some(n) => {
-                                let _ = self.go_to_floor(n).await;
-
-                                /* remove from destination map */
-                                let mut destination_map = self.destination_map.lock().await;
-                                destination_map.remove(&n);
-                            }
-                            None => {}
-                        }
-                    }
+        self.cancel_going_home().await;
+
+        /* both the pick up and the drop off are served while travelling the passenger's way */
+        let mut stops = self.stops(request.direction).lock().await;
+
+        /* already queued floors are dropped by the set */
+        log!("appending to queue : {}", request);
+        stops.insert(request.from);
+        if let Some(to) = request.to {
+            stops.insert(to);
+        }
+        drop(stops);
+
+        self.waiting.lock().await.push(request);
+        self.wake().await;
+    }
+
+    /* start serving the queued stops, unless a worker already does */
+    async fn wake(&self) {
+        /* if busy, quit. will be processed soon */
+        let mut busy = self.is_busy.lock().await;
+        if *busy {
+            return;
+        }
+
+        *busy = true;
+        drop(busy);
+
+        /* serve in the background, so requests made while moving are still received */
+        let worker = self.clone();
+        tokio::spawn(async move {
+            worker.serve_stops().await;
+        });
+    }
+
+    /* stop at the floor, the central controller worked out which way it lies from the car */
+    async fn press_car_call(&self, floor: usize, direction: Direction) {
+        /* sent before central controller knew */
+        if *self.mode.lock().await != ServiceMode::Normal {
+            return;
+        }
+
+        log!("Elevator {} car call to {}", self.id, floor);
+        self.cancel_going_home().await;
+        self.car_calls.lock().await.insert(floor);
+        self.stops(direction).lock().await.insert(floor);
+        self.wake().await;
+    }
+
+    /* a door cycle in progress takes the command, an idle car opens on the open button */
+    async fn press_door_button(&self, command: DoorCommand) {
+        if matches!(*self.mode.lock().await, ServiceMode::FireRecall | ServiceMode::Firefighter) {
+            return;
+        }
+
+        let mut busy = self.is_busy.lock().await;
+        if *busy || command != DoorCommand::Open {
+            self.door.command(command);
+            return;
+        }
+
+        *busy = true;
+        drop(busy);
+
+        /* requests arriving meanwhile are queued, the worker serves them once the doors are closed */
+        let worker = self.clone();
+        tokio::spawn(async move {
+            {
+                let mut elevator = worker.state.lock().await;
+                worker.door.open(&mut elevator).await;
+                worker.door.dwell(&mut elevator).await;
+                worker.door.close(&mut elevator).await;
+            }
+            worker.serve_stops().await;
+        });
+    }
+
+    /* waiting passengers go to other cars, riders finish their trip or all get off at the evacuation floor */
+    async fn take_out_of_service(&self, evacuate_to: Option<usize>) {
+        *self.mode.lock().await = ServiceMode::OutOfService;
+        self.cancel_going_home().await;
+
+        let mut waiting = self.waiting.lock().await;
+        let mut riding = self.riding.lock().await;
+        let handed_back: Vec<ElevatorRequest> = waiting.drain(..).collect();
+        self.drop_stops(&handed_back, &waiting, &riding).await;
+
+        if let Some(floor) = evacuate_to {
+            let mut leaving = self.leaving.lock().await;
+            for rider in riding.drain(..) {
+                self.progress(&rider, RequestStatus::Cancelled);
+                leaving.push(rider);
+            }
+
+            drop(leaving);
+            self.head_for(floor).await;
+        }
+
+        drop(riding);
+        drop(waiting);
+        self.hand_back(handed_back);
+
+        let is_idle = Self::is_idle(&*self.up_stops.lock().await, &*self.down_stops.lock().await);
+        if !is_idle {
+            self.wake().await;
+            return;
+        }
 
-                    let mut busy = self.is_busy.lock().await;
-                    *busy = false
+        /* nothing to finish, park right away unless a worker is about to */
+        if !*self.is_busy.lock().await {
+            self.in_background(|car| async move {
+                let mut elevator = car.state.lock().await;
+                if *car.mode.lock().await == ServiceMode::OutOfService {
+                    elevator.initial_direction = elevator.direction;
+                    elevator.direction = Direction::OutOfService;
+                    let _ = car.state_transmitter.send(elevator.clone());
                 }
-                Err(e) => {
-                    print!("error receiving signal {}", e)
+            });
+        }
+    }
+
+    async fn return_to_service(&self) {
+        *self.mode.lock().await = ServiceMode::Normal;
+
+        self.in_background(|car| async move {
+            let mut elevator = car.state.lock().await;
+            if *car.mode.lock().await != ServiceMode::Normal {
+                return;
+            }
+
+            if elevator.direction == Direction::OutOfService {
+                elevator.initial_direction = Direction::OutOfService;
+                elevator.direction = Direction::Idle;
+            }
+
+            /* also after a fault, the central controller puts the car back into its pool */
+            let _ = car.state_transmitter.send(elevator.clone());
+        });
+    }
+
+    /* the worker holds the car's state for a whole trip, the signal loop must not wait for it */
+    /* the task checks the mode again once it has the state, a later signal may have changed it */
+    fn in_background<F, Fut>(&self, action: F)
+    where
+        F: FnOnce(ElevatorController) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(action(self.clone()));
+    }
+
+    /* what an idle car reports, parked cars stay out of dispatch */
+    async fn resting_direction(&self) -> Direction {
+        match *self.mode.lock().await {
+            ServiceMode::Normal => Direction::Idle,
+            ServiceMode::OutOfService => Direction::OutOfService,
+            ServiceMode::FireRecall | ServiceMode::Firefighter => Direction::Stopped,
+        }
+    }
+
+    /* an idle car moves to its home floor, a car with stops left ignores it */
+    async fn go_home(&self, floor: usize) {
+        if *self.mode.lock().await != ServiceMode::Normal {
+            return;
+        }
+
+        /* the central controller never sends the floor the car stands at, a stale one is a stop with the doors shut */
+        /* a worker finishing its last stop picks the floor up, otherwise a new one starts */
+        let mut up_stops = self.up_stops.lock().await;
+        let mut down_stops = self.down_stops.lock().await;
+        if !Self::is_idle(&up_stops, &down_stops) {
+            return;
+        }
+
+        log!("Elevator {} heading home to {}", self.id, floor);
+        *self.heading_home.lock().await = Some(floor);
+        up_stops.insert(floor);
+        down_stops.insert(floor);
+
+        drop(down_stops);
+        drop(up_stops);
+        self.wake().await;
+    }
+
+    /* a real call came in, the car stops wherever it is and serves it instead */
+    async fn cancel_going_home(&self) {
+        let Some(home) = self.heading_home.lock().await.take() else {
+            return;
+        };
+
+        log!("Elevator {} no longer heading home to {}", self.id, home);
+        self.up_stops.lock().await.remove(&home);
+        self.down_stops.lock().await.remove(&home);
+    }
+
+    /* stop taking calls until told otherwise */
+    fn park(&self, elevator: &mut ElevatorState) {
+        log!("Elevator {} parked at {}", elevator.id, elevator.current_floor);
+        elevator.initial_direction = elevator.direction;
+        elevator.direction = Direction::Stopped;
+        let _ = self.state_transmitter.send(elevator.clone());
+    }
+
+    /* forget every stop, the floor is the only place left to go */
+    async fn head_for(&self, floor: usize) {
+        let mut up_stops = self.up_stops.lock().await;
+        let mut down_stops = self.down_stops.lock().await;
+        up_stops.clear();
+        down_stops.clear();
+        self.car_calls.lock().await.clear();
+        *self.heading_home.lock().await = None;
+
+        /* queued both ways so the car heads there from wherever it is */
+        up_stops.insert(floor);
+        down_stops.insert(floor);
+    }
+
+    /* phase I, every passenger's call is void, riders get out at the recall floor */
+    async fn recall(&self, floor: usize) {
+        *self.mode.lock().await = ServiceMode::FireRecall;
+
+        let mut waiting = self.waiting.lock().await;
+        let mut riding = self.riding.lock().await;
+        let mut leaving = self.leaving.lock().await;
+
+        for passenger in waiting.drain(..) {
+            self.progress(&passenger, RequestStatus::Cancelled);
+        }
+
+        for rider in riding.drain(..) {
+            self.progress(&rider, RequestStatus::Cancelled);
+            leaving.push(rider);
+        }
+
+        drop(leaving);
+        drop(riding);
+        drop(waiting);
+
+        log!("Elevator {} recalled to {}", self.id, floor);
+        self.head_for(floor).await;
+        self.wake().await;
+    }
+
+    /* phase II, the firefighter sends the car to a floor, it arrives with its doors closed */
+    async fn firefighter_go_to(&self, floor: usize) {
+        *self.mode.lock().await = ServiceMode::Firefighter;
+
+        self.head_for(floor).await;
+        self.wake().await;
+    }
+
+    async fn firefighter_door(&self, open: bool) {
+        if *self.mode.lock().await != ServiceMode::Firefighter {
+            return;
+        }
+
+        /* applied once the car stands still, only the last command pressed meanwhile counts */
+        *self.firefighter_door.lock().await = Some(open);
+        self.in_background(|car| async move {
+            let mut elevator = car.state.lock().await;
+            let Some(open) = car.firefighter_door.lock().await.take() else {
+                return;
+            };
+
+            if *car.mode.lock().await != ServiceMode::Firefighter {
+                return;
+            }
+
+            if open {
+                car.door.open(&mut elevator).await;
+            } else {
+                car.door.close(&mut elevator).await;
+            }
+        });
+    }
+
+    /* back to normal service, doors close and the car reports idle */
+    async fn end_recall(&self) {
+        *self.mode.lock().await = ServiceMode::Normal;
+        {
+            let mut up_stops = self.up_stops.lock().await;
+            let mut down_stops = self.down_stops.lock().await;
+            up_stops.clear();
+            down_stops.clear();
+            self.car_calls.lock().await.clear();
+        }
+
+        self.in_background(|car| async move {
+            let mut elevator = car.state.lock().await;
+            if *car.mode.lock().await != ServiceMode::Normal {
+                return;
+            }
+
+            if elevator.door != DoorState::Closed {
+                car.door.close(&mut elevator).await;
+            }
+
+            if elevator.direction != Direction::Idle {
+                elevator.initial_direction = elevator.direction;
+                elevator.direction = Direction::Idle;
+            }
+            let _ = car.state_transmitter.send(elevator.clone());
+        });
+    }
+
+    /* forget a passenger, its floors stay queued only while another passenger needs them */
+    async fn cancel(&self, request_id: &str) {
+        let mut waiting = self.waiting.lock().await;
+        let mut riding = self.riding.lock().await;
+
+        let cancelled = if let Some(index) = waiting.iter().position(|p| p.id == request_id) {
+            waiting.remove(index)
+        } else if let Some(index) = riding.iter().position(|p| p.id == request_id) {
+            /* already in the car, it gets off wherever the car opens next */
+            let rider = riding.remove(index);
+            self.leaving.lock().await.push(rider.clone());
+            rider
+        } else {
+            return;
+        };
+
+        log!("cancelled {}", cancelled);
+        self.drop_stops(&[cancelled], &waiting, &riding).await;
+    }
+
+    /* keep going while there's a queued stop (probably added while moving) */
+    async fn serve_stops(&self) {
+        let mut heading = match self.state.lock().await.direction {
+            Direction::Down => Direction::Down,
+            _ => Direction::Up,
+        };
+
+        loop {
+            /* checked under the busy lock, a request arriving right now either sees us busy or spawns a new worker */
+            let mut busy = self.is_busy.lock().await;
+            let current_floor = self.state.lock().await.current_floor;
+            let next = self.next_stop(current_floor, heading).await;
+
+            let Some(next) = next else {
+                *busy = false;
+                break;
+            };
+            drop(busy);
+
+            let _ = self.go_to_floor(next).await;
+
+            heading = match self.state.lock().await.direction {
+                Direction::Down => Direction::Down,
+                Direction::Up => Direction::Up,
+                _ => heading,
+            };
+        }
+    }
+
+    /* LOOK: the nearest stop ahead in the current heading, otherwise the farthest stop to turn around at */
+    async fn next_stop(&self, current_floor: usize, heading: Direction) -> Option<usize> {
+        let up_stops = self.up_stops.lock().await;
+        let down_stops = self.down_stops.lock().await;
+
+        let going_up = || {
+            up_stops.range(current_floor..).next().copied().or_else(|| {
+                down_stops.range(current_floor..).next_back().copied()
+            })
+        };
+
+        let going_down = || {
+            down_stops.range(..=current_floor).next_back().copied().or_else(|| {
+                up_stops.range(..=current_floor).next().copied()
+            })
+        };
+
+        if heading == Direction::Down {
+            going_down().or_else(going_up)
+        } else {
+            going_up().or_else(going_down)
+        }
+    }
+
+    /* is the floor queued for a car passing it in this direction */
+    async fn is_stop(&self, floor: usize, direction: Direction) -> bool {
+        match direction {
+            Direction::Up => self.up_stops.lock().await.contains(&floor),
+            Direction::Down => self.down_stops.lock().await.contains(&floor),
+            _ => false,
+        }
+    }
+
+    /* the car stopped at a floor, clear the stops it just served, returns whether it turns around here */
+    async fn serve_floor(&self, floor: usize, direction: Direction) -> bool {
+        let mut up_stops = self.up_stops.lock().await;
+        let mut down_stops = self.down_stops.lock().await;
+
+        let turns_around = match direction {
+            Direction::Up => {
+                up_stops.remove(&floor);
+                up_stops.range(floor..).next().is_none() && down_stops.range(floor + 1..).next().is_none()
+            }
+            Direction::Down => {
+                down_stops.remove(&floor);
+                down_stops.range(..floor).next().is_none() && up_stops.range(..floor).next().is_none()
+            }
+            _ => true,
+        };
+
+        /* nothing left ahead, the car leaves the other way, so the passengers waiting for that way get in */
+        if turns_around {
+            up_stops.remove(&floor);
+            down_stops.remove(&floor);
+        }
+
+        turns_around
+    }
+
+    /* the door is open, riders for this floor get off and waiting passengers going the car's way get in */
+    /* returns the passengers that did not fit, they are handed back once the new load is published */
+    async fn exchange_passengers(&self, elevator: &mut ElevatorState, turns_around: bool) -> Vec<ElevatorRequest> {
+        let floor = elevator.current_floor;
+        let mut waiting = self.waiting.lock().await;
+        let mut riding = self.riding.lock().await;
+        let mut left_behind = Vec::new();
+
+        self.leaving.lock().await.clear();
+        self.car_calls.lock().await.remove(&floor);
+        riding.retain(|passenger| {
+            let alights = passenger.to == Some(floor);
+            if alights {
+                self.progress(passenger, RequestStatus::Arrived(elevator.id));
+            }
+            !alights
+        });
+        elevator.current_load = riding.len();
+
+        /* a passenger not boarding keeps its stop, the car comes back for it */
+        let mut index = 0;
+        while index < waiting.len() {
+            let passenger = &waiting[index];
+            let direction = passenger.direction;
+
+            if passenger.from == floor && (turns_around || direction == elevator.direction) {
+                let passenger = waiting.remove(index);
+
+                if !elevator.has_room() {
+                    left_behind.push(passenger);
+                    continue;
+                }
+
+                self.progress(&passenger, RequestStatus::Boarded(elevator.id));
+
+                match passenger.to {
+                    /* a hall button was answered, the destination comes as a car call */
+                    None => {}
+                    /* a passenger calling to its own floor just steps in and out */
+                    Some(to) if to == floor => {
+                        self.progress(&passenger, RequestStatus::Arrived(elevator.id));
+                    }
+                    Some(to) => {
+                        /* its floor may have been passed while the car came for it, press it again from inside */
+                        self.stops(direction).lock().await.insert(to);
+                        riding.push(passenger);
+                        elevator.current_load = riding.len();
+                    }
+                }
+            } else {
+                index += 1;
+            }
+        }
+
+        log!("Elevator {} load {}", elevator.id, elevator.current_load);
+        self.drop_stops(&left_behind, &waiting, &riding).await;
+        left_behind
+    }
+
+    /* a full car only stops where someone gets off, the hall calls here go to another car */
+    async fn passes_by(&self, elevator: &ElevatorState, floor: usize) -> bool {
+        if elevator.has_room() {
+            return false;
+        }
+
+        let mut waiting = self.waiting.lock().await;
+        let riding = self.riding.lock().await;
+        if riding.iter().any(|passenger| passenger.to == Some(floor)) || self.car_calls.lock().await.contains(&floor) {
+            return false;
+        }
+
+        let (passed, kept): (Vec<ElevatorRequest>, Vec<ElevatorRequest>) = waiting
+            .drain(..)
+            .partition(|passenger| passenger.from == floor && passenger.direction == elevator.direction);
+        *waiting = kept;
+
+        self.drop_stops(&passed, &waiting, &riding).await;
+        drop(riding);
+        drop(waiting);
+
+        self.hand_back(passed);
+        true
+    }
+
+    /* forget the floors only the handed back passengers needed, car calls stay */
+    async fn drop_stops(&self, passengers: &[ElevatorRequest], waiting: &[ElevatorRequest], riding: &[ElevatorRequest]) {
+        /* car_calls is released before the stop sets are locked, head_for takes them the other way round */
+        let car_calls = self.car_calls.lock().await.clone();
+
+        for passenger in passengers {
+            let direction = passenger.direction;
+
+            for floor in [Some(passenger.from), passenger.to].into_iter().flatten() {
+                let still_needed = car_calls.contains(&floor)
+                    || riding.iter().any(|p| p.direction == direction && p.to == Some(floor))
+                    || waiting.iter().any(|p| p.direction == direction && (p.from == floor || p.to == Some(floor)));
+
+                if !still_needed {
+                    self.stops(direction).lock().await.remove(&floor);
                 }
             }
         }
     }
+
+    /* send hall calls this car could not take back to the central controller */
+    fn hand_back(&self, passengers: Vec<ElevatorRequest>) {
+        for passenger in passengers {
+            log!("Elevator {} handing back {}", self.id, passenger);
+            let _ = self.event_transmitter.send(ElevatorEvent::HandBack(self.id, passenger));
+        }
+    }
+
+    fn progress(&self, passenger: &ElevatorRequest, status: RequestStatus) {
+        let _ = self.event_transmitter.send(ElevatorEvent::Progress(passenger.id.clone(), status));
+    }
+
+    /* tell the passengers waiting at the floor the car is heading to that it is coming */
+    async fn announce_arrival(&self, elevator: &ElevatorState, floor: usize) {
+        for passenger in self.waiting.lock().await.iter().filter(|p| p.from == floor) {
+            self.progress(passenger, RequestStatus::CarArriving(elevator.id));
+        }
+    }
+
+    fn stops(&self, direction: Direction) -> &Arc<Mutex<BTreeSet<usize>>> {
+        match direction {
+            Direction::Down => &self.down_stops,
+            _ => &self.up_stops,
+        }
+    }
+
+    /* the floor is still queued in either direction */
+    async fn is_planned(&self, floor: usize) -> bool {
+        self.up_stops.lock().await.contains(&floor) || self.down_stops.lock().await.contains(&floor)
+    }
+
+    fn is_idle(up_stops: &BTreeSet<usize>, down_stops: &BTreeSet<usize>) -> bool {
+        up_stops.is_empty() && down_stops.is_empty()
+    }
 }
 
 impl ElevatorControllerI for ElevatorController {
-    async fn go_to_floor(&self, destination: usize) -> Result<(), Error> {
+    async fn go_to_floor(&self, destination: usize) -> Result<(), DispatchError> {
         let mut elevator = self.state.lock().await;
 
-        if destination == elevator.current_floor {
-            let _ = elevator.open_door().await;
-            let _ = self.state_transmitter.send(elevator.clone());
-            tokio::task::yield_now().await;
+        if destination != elevator.current_floor {
+            /* a parked car may still have its doors open */
+            if elevator.door != DoorState::Closed {
+                self.door.close(&mut elevator).await;
+            }
 
-            sleep(Duration::from_secs(5)).await;
-            _ = elevator.close_door().await;
-            let ok = self.state_transmitter.send(elevator.clone());
-            return Ok(());
-        }
+            elevator.initial_direction = elevator.direction;
+            self.announce_arrival(&elevator, destination).await;
 
-        elevator.initial_direction = elevator.direction.clone();
+            if destination > elevator.current_floor {
+                elevator.direction = Direction::Up
+            } else {
+                elevator.direction = Direction::Down
+            }
 
-        if destination > elevator.current_floor {
-            elevator.direction = "up".to_string()
-        } else {
-            elevator.direction = "down".to_string()
-        }
+            elevator.is_moving = true;
+
+            let start_floor = elevator.current_floor;
+            let mut current_floor = elevator.current_floor;
+            loop {
+                /* artificial delay, mimick a moving elevator */
+                self.clock.sleep(FLOOR_TRAVEL).await;
+                elevator.current_floor = current_floor;
+
+                /* send the state after movement */
+                let ok = self.state_transmitter.send(elevator.clone());
+                match ok {
+                    Ok(_) => {}
+                    Err(e) => {
+                        log!(
+                            "got error on publishing elevator state {} {}",
+                            elevator.id, e
+                        );
+                    }
+                }
+                tokio::task::yield_now().await;
 
-        elevator.is_moving = true;
+                elevator.initial_direction = elevator.direction;
 
-        let mut current_floor = elevator.current_floor;
-        loop {
-            /* artificial delay, mimick a moving elevator */
-            sleep(Duration::from_millis(1500)).await;
-            elevator.current_floor = current_floor;
+                /* the call that sent the car here was cancelled, stop here and plan again */
+                if !self.is_planned(destination).await {
+                    let is_idle = Self::is_idle(&*self.up_stops.lock().await, &*self.down_stops.lock().await);
 
-            /* send the state after movement */
-            let ok = self.state_transmitter.send(elevator.clone());
-            match ok {
-                Ok(_) => {}
-                Err(e) => {
-                    println!(
-                        "got error on publishing elevator state {} {}",
-                        elevator.id, e
+                    /* riders that cancelled get out here, unless the car stops somewhere else anyway */
+                    if is_idle && !self.leaving.lock().await.is_empty() {
+                        break;
+                    }
+
+                    log!(
+                        "Elevator {} no longer needed at {}, stopping at {}",
+                        elevator.id, destination, current_floor
+                    );
+                    elevator.is_moving = false;
+
+                    if is_idle {
+                        log!("Elevator becomes idle: {}", elevator.id);
+                        elevator.direction = self.resting_direction().await;
+                        let _ = self.state_transmitter.send(elevator.clone());
+                        tokio::task::yield_now().await;
+                    }
+                    return Ok(());
+                }
+
+                if current_floor == destination {
+                    log!(
+                        "Elevator {} arrived at destination {}",
+                        elevator.id, destination
                     );
+                    break;
+                }
+
+                /* someone is waiting here for this direction, stop on the way */
+                if current_floor != start_floor
+                    && self.is_stop(current_floor, elevator.direction).await
+                    && !self.passes_by(&elevator, current_floor).await
+                {
+                    log!(
+                        "Elevator {} stopping at {} on the way to {}",
+                        elevator.id, current_floor, destination
+                    );
+                    break;
+                }
+
+                /* decide where to go next */
+                match elevator.direction {
+                    Direction::Up => current_floor += 1,
+                    Direction::Down => current_floor -= 1,
+                    _ => {}
                 }
             }
+
             tokio::task::yield_now().await;
+            elevator.is_moving = false;
+        }
 
-            elevator.initial_direction = elevator.direction.clone();
-            if current_floor == destination {
-                println!(
-                    "Elevator {} arrived at destination {}",
-                    elevator.id, destination
-                );
-                break;
-            }
+        let current_floor = elevator.current_floor;
+        let turns_around = self.serve_floor(current_floor, elevator.direction).await;
+        let mode = *self.mode.lock().await;
 
-            /* decide where to go next */
-            if elevator.direction == "up".to_string() {
-                current_floor = current_floor + 1;
-            } else if elevator.direction == "down".to_string() {
-                current_floor = current_floor - 1;
-            }
+        /* under firefighter control the doors only move on command */
+        if mode == ServiceMode::Firefighter {
+            self.park(&mut elevator);
+            return Ok(());
         }
 
-        tokio::task::yield_now().await;
-        elevator.is_moving = false;
+        /* nobody waits at a home floor, the doors stay shut */
+        let arrived_home = self.heading_home.lock().await.take_if(|home| *home == current_floor).is_some();
 
         /* open and close the door */
-        _ = elevator.open_door().await;
+        if !arrived_home {
+            self.door.open(&mut elevator).await;
+            let left_behind = self.exchange_passengers(&mut elevator, turns_around).await;
 
-        let _ = self.state_transmitter.send(elevator.clone());
-        tokio::task::yield_now().await;
+            let _ = self.state_transmitter.send(elevator.clone());
+            tokio::task::yield_now().await;
+            self.hand_back(left_behind);
 
-        sleep(Duration::from_secs(5)).await;
-        _ = elevator.close_door().await;
+            /* recalled cars park at the recall floor with their doors open */
+            if mode == ServiceMode::FireRecall {
+                self.park(&mut elevator);
+                return Ok(());
+            }
 
-        let _ = self.state_transmitter.send(elevator.clone());
+            self.door.dwell(&mut elevator).await;
+            self.door.close(&mut elevator).await;
+        }
 
-        /* elevator becomes idle? */
-        if self.destination_list.lock().await.len() == 0 as usize {
-            println!("Elevator becomes idle: {}", elevator.id);
-            elevator.direction = "idle".to_string();
+        /* elevator becomes idle? a car that was already resting stays where it is in the pools */
+        let resting_direction = self.resting_direction().await;
+        let is_idle = Self::is_idle(&*self.up_stops.lock().await, &*self.down_stops.lock().await);
+        if is_idle && elevator.direction != resting_direction {
+            log!("Elevator becomes idle: {}", elevator.id);
+            elevator.initial_direction = elevator.direction;
+            elevator.direction = resting_direction;
 
             /* send the state after idle */
             let ok = self.state_transmitter.send(elevator.clone());
@@ -206,7 +843,7 @@ impl ElevatorControllerI for ElevatorController {
             match ok {
                 Ok(_) => {}
                 Err(e) => {
-                    println!(
+                    log!(
                         "got error on publishing elevator state {} {}",
                         elevator.id, e
                     );
@@ -217,3 +854,96 @@ impl ElevatorControllerI for ElevatorController {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::channel;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    fn car() -> (ElevatorController, VirtualClock, Receiver<ElevatorState>) {
+        let clock = VirtualClock::new();
+        let (state_tx, state_rx) = channel(1024);
+        let (event_tx, _) = channel(1024);
+        let car = ElevatorController::new(0, Capacity::default(), state_tx, event_tx, Arc::new(clock.clone()));
+        (car, clock, state_rx)
+    }
+
+    fn hall_call(from: usize, direction: Direction) -> ElevatorRequest {
+        ElevatorRequest { id: format!("{} {:?}", from, direction), from, to: None, direction }
+    }
+
+    /* let the worker run until it waits on nothing but itself */
+    async fn run(clock: &VirtualClock) {
+        loop {
+            for _ in 0..32 {
+                tokio::task::yield_now().await;
+            }
+
+            if clock.advance_to_next_wakeup().is_none() {
+                break;
+            }
+        }
+    }
+
+    /* the floors the car opened its doors at, in order */
+    fn stops_made(state_rx: &mut Receiver<ElevatorState>) -> Vec<usize> {
+        let mut floors = Vec::new();
+        while let Ok(state) = state_rx.try_recv() {
+            if state.door == DoorState::Opening {
+                floors.push(state.current_floor);
+            }
+        }
+        floors
+    }
+
+    #[tokio::test]
+    async fn next_stop_is_the_nearest_ahead_then_the_farthest_behind() {
+        let (car, _, _) = car();
+        car.up_stops.lock().await.extend([7]);
+        car.down_stops.lock().await.extend([2, 6]);
+
+        assert_eq!(car.next_stop(5, Direction::Up).await, Some(7));
+
+        /* nothing left above going up, the highest down stop is where the car turns */
+        car.up_stops.lock().await.remove(&7);
+        assert_eq!(car.next_stop(7, Direction::Up).await, Some(6));
+
+        car.down_stops.lock().await.remove(&6);
+        assert_eq!(car.next_stop(6, Direction::Down).await, Some(2));
+
+        /* going down with only up stops, the lowest one first */
+        car.down_stops.lock().await.clear();
+        car.up_stops.lock().await.extend([1, 4, 8]);
+        assert_eq!(car.next_stop(5, Direction::Down).await, Some(1));
+        assert_eq!(car.next_stop(5, Direction::Up).await, Some(8));
+    }
+
+    #[tokio::test]
+    async fn stops_are_served_in_floor_order_not_call_order() {
+        let (car, clock, mut state_rx) = car();
+
+        car.accept(hall_call(4, Direction::Up)).await;
+        car.accept(hall_call(1, Direction::Up)).await;
+        car.press_car_call(3, Direction::Up).await;
+        run(&clock).await;
+
+        assert_eq!(stops_made(&mut state_rx), vec![1, 3, 4]);
+        assert!(ElevatorController::is_idle(&*car.up_stops.lock().await, &*car.down_stops.lock().await));
+    }
+
+    #[tokio::test]
+    async fn callers_going_down_are_served_after_the_turn() {
+        let (car, clock, mut state_rx) = car();
+
+        /* the car passes 2 going up, turns at 5 and picks 2 up on the way down */
+        car.accept(hall_call(5, Direction::Down)).await;
+        car.accept(hall_call(2, Direction::Down)).await;
+        car.press_car_call(3, Direction::Up).await;
+        run(&clock).await;
+
+        assert_eq!(stops_made(&mut state_rx), vec![3, 5, 2]);
+        assert_eq!(car.state.lock().await.current_floor, 2);
+    }
+}
@@ -6,7 +6,7 @@
 //    2    5
 //   /  \ /  \
 //  7   6 9  10
-use crate::{elevator_controller::ElevatorController, interfaces::ElevatorPool};
+use crate::{dispatch_error::DispatchError, elevator::ElevatorState, interfaces::ElevatorPool};
 use std::{
     collections::{HashMap, VecDeque},
     fmt::Debug,
@@ -16,13 +16,13 @@ use tokio::sync::Mutex;
 
 #[derive(Debug)]
 pub struct ElevatorHeap {
-    elevators: Arc<Mutex<VecDeque<ElevatorController>>>,
-    pub elevators_index: Arc<Mutex<HashMap<usize, usize>>>,
+    elevators: Arc<Mutex<VecDeque<ElevatorState>>>,
+    pub elevators_index: Arc<Mutex<HashMap<usize, usize>>>, // elevator id -> position in the heap
 }
 
 impl ElevatorPool for ElevatorHeap {
     async fn len(&self) -> usize {
-        return self.elevators.lock().await.len();
+        self.elevators.lock().await.len()
     }
 
     fn new() -> Self {
@@ -31,50 +31,273 @@ impl ElevatorPool for ElevatorHeap {
             elevators_index: Arc::new(Mutex::new(HashMap::new())),
         }
     }
-    
-    
-    async fn get_elevator(&mut self) -> Option<crate::elevator::ElevatorState> {
-        todo!()
+
+    async fn get_elevator(&mut self) -> Option<ElevatorState> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        if elevators.is_empty() {
+            return None;
+        }
+
+        /* move the last leaf to the root, then let it sink */
+        let last = elevators.len() - 1;
+        elevators.swap(0, last);
+        let elevator = elevators.pop_back()?;
+        elevators_index.remove(&elevator.id);
+
+        let has_root = !elevators.is_empty();
+        if has_root {
+            elevators_index.insert(elevators[0].id, 0);
+        }
+
+        drop(elevators_index);
+        drop(elevators);
+
+        if has_root {
+            let _ = self.bubble_down(0).await;
+        }
+
+        Some(elevator)
     }
-    
-    async fn insert_elevator(&mut self, elevator: crate::elevator::ElevatorState) -> Result<(), MyError> {
-        todo!()
+
+    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), DispatchError> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+        let elevator_id = elevator.id;
+
+        /* already in the heap, refresh its load and restore the heap order */
+        if let Some(index) = elevators_index.get(&elevator_id).copied() {
+            elevators[index] = elevator;
+            drop(elevators_index);
+            drop(elevators);
+
+            let index = self.bubble_up(index).await.ok_or(DispatchError::CarMissing(elevator_id))?;
+            self.bubble_down(index).await.ok_or(DispatchError::CarMissing(elevator_id))?;
+            return Ok(());
+        }
+
+        let index = elevators.len();
+        elevators_index.insert(elevator_id, index);
+        elevators.push_back(elevator);
+
+        drop(elevators_index);
+        drop(elevators);
+
+        self.bubble_up(index).await.ok_or(DispatchError::CarMissing(elevator_id))?;
+        Ok(())
     }
-    
-    async fn remove_elevator(&mut self, elevator_id: usize) -> Option<crate::elevator::ElevatorState> {
-        todo!()
+
+    async fn remove_elevator(&mut self, elevator_id: usize) -> Option<ElevatorState> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        let index = elevators_index.remove(&elevator_id)?;
+
+        /* replace the removed node with the last leaf */
+        let last = elevators.len() - 1;
+        elevators.swap(index, last);
+        let elevator = elevators.pop_back();
+
+        let needs_fixing = index < elevators.len();
+        if needs_fixing {
+            elevators_index.insert(elevators[index].id, index);
+        }
+
+        drop(elevators_index);
+        drop(elevators);
+
+        /* the moved leaf may belong either above or below its new position */
+        if needs_fixing {
+            let index = self.bubble_up(index).await.unwrap_or(index);
+            let _ = self.bubble_down(index).await;
+        }
+
+        elevator
     }
 
+    async fn list_elevators(&self) -> Vec<ElevatorState> {
+        self.elevators.lock().await.iter().cloned().collect()
+    }
 
+    async fn peek_elevator(&self) -> Option<ElevatorState> {
+        self.elevators.lock().await.front().cloned()
+    }
 }
 
-#[derive(Debug)]
-pub struct MyError {
-    message: String,
-}
+impl ElevatorHeap {
+    /* swap two nodes and keep the index pointing to their new positions */
+    fn swap(
+        elevators: &mut VecDeque<ElevatorState>,
+        elevators_index: &mut HashMap<usize, usize>,
+        a: usize,
+        b: usize,
+    ) {
+        elevators.swap(a, b);
+        elevators_index.insert(elevators[a].id, a);
+        elevators_index.insert(elevators[b].id, b);
+    }
+
+    /* move a node up while it is lighter than its parent, returns its final position, None when there is no such node */
+    async fn bubble_up(&mut self, index: usize) -> Option<usize> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
 
-impl std::fmt::Display for MyError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        if index >= elevators.len() {
+            return None;
+        }
+
+        let mut index = index;
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if elevators[parent].current_load <= elevators[index].current_load {
+                break;
+            }
+
+            Self::swap(&mut elevators, &mut elevators_index, parent, index);
+            index = parent;
+        }
+
+        Some(index)
+    }
+
+    /* move a node down while one of its children is lighter, returns its final position, None when there is no such node */
+    async fn bubble_down(&mut self, index: usize) -> Option<usize> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        if index >= elevators.len() {
+            return None;
+        }
+
+        let mut index = index;
+        loop {
+            let left = 2 * index + 1;
+            let right = left + 1;
+            let mut lightest = index;
+
+            if left < elevators.len()
+                && elevators[left].current_load < elevators[lightest].current_load
+            {
+                lightest = left;
+            }
+
+            if right < elevators.len()
+                && elevators[right].current_load < elevators[lightest].current_load
+            {
+                lightest = right;
+            }
+
+            if lightest == index {
+                break;
+            }
+
+            Self::swap(&mut elevators, &mut elevators_index, index, lightest);
+            index = lightest;
+        }
+
+        Some(index)
     }
 }
 
-impl std::error::Error for MyError {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::Capacity;
 
-impl ElevatorHeap {
-    async fn bubble_up(&mut self, index: usize) -> (usize, Option<MyError>) {
+    fn car(id: usize, current_load: usize) -> ElevatorState {
+        ElevatorState { current_load, ..ElevatorState::new(id, Capacity::default()) }
+    }
+
+    async fn heap_of(loads: &[usize]) -> ElevatorHeap {
+        let mut heap = ElevatorHeap::new();
+        for (id, load) in loads.iter().enumerate() {
+            heap.insert_elevator(car(id, *load)).await.unwrap();
+            assert_invariants(&heap).await;
+        }
+        heap
+    }
+
+    /* every car's index points at its slot, and no car is lighter than its parent */
+    async fn assert_invariants(heap: &ElevatorHeap) {
+        let elevators = heap.elevators.lock().await;
+        let elevators_index = heap.elevators_index.lock().await;
 
+        assert_eq!(elevators_index.len(), elevators.len());
+        for (slot, elevator) in elevators.iter().enumerate() {
+            assert_eq!(elevators_index.get(&elevator.id), Some(&slot), "car {} is not indexed at slot {}", elevator.id, slot);
+            if slot > 0 {
+                assert!(elevators[(slot - 1) / 2].current_load <= elevator.current_load, "car {} is lighter than its parent", elevator.id);
+            }
+        }
+    }
+
+    #[test]
+    fn swap_keeps_the_index_on_the_slots() {
+        let mut elevators: VecDeque<ElevatorState> = [car(7, 0), car(8, 1), car(9, 2)].into_iter().collect();
+        let mut elevators_index = HashMap::from([(7, 0), (8, 1), (9, 2)]);
+
+        ElevatorHeap::swap(&mut elevators, &mut elevators_index, 0, 2);
+
+        assert_eq!(elevators[0].id, 9);
+        assert_eq!(elevators[2].id, 7);
+        assert_eq!(elevators_index, HashMap::from([(9, 0), (8, 1), (7, 2)]));
+    }
+
+    #[tokio::test]
+    async fn lighter_cars_bubble_up_to_the_root() {
+        let heap = heap_of(&[9, 7, 5, 3, 1]).await;
+
+        assert_eq!(heap.peek_elevator().await.map(|e| e.id), Some(4));
+        assert_eq!(*heap.elevators_index.lock().await.get(&4).unwrap(), 0);
+    }
 
-        return (
-            0,
-            Some(MyError {
-                message: "parent is missing".to_string(),
-            }),
-        );
+    #[tokio::test]
+    async fn the_root_sinks_back_after_every_get() {
+        let mut heap = heap_of(&[4, 2, 6, 0, 5, 3, 1]).await;
+
+        let mut loads = Vec::new();
+        while let Some(elevator) = heap.get_elevator().await {
+            assert!(!heap.elevators_index.lock().await.contains_key(&elevator.id));
+            assert_invariants(&heap).await;
+            loads.push(elevator.current_load);
+        }
+
+        assert_eq!(loads, vec![0, 1, 2, 3, 4, 5, 6]);
     }
 
-    async fn bubble_down(&mut self, index: usize) -> (usize, Option<MyError>) {
+    #[tokio::test]
+    async fn removing_from_the_middle_reindexes_the_moved_leaf() {
+        /* slots follow the ids, car 6 is the last leaf */
+        let mut heap = heap_of(&[0, 5, 1, 6, 7, 2, 3]).await;
+
+        /* car 6 fills slot 3 and is lighter than car 1 above it */
+        assert_eq!(heap.remove_elevator(3).await.map(|e| e.id), Some(3));
+        assert_invariants(&heap).await;
+        assert_eq!(heap.elevators_index.lock().await.get(&6), Some(&1));
+        assert_eq!(heap.elevators_index.lock().await.get(&1), Some(&3));
+
+        /* car 5 fills the root and is heavier than car 2 below it */
+        assert_eq!(heap.remove_elevator(0).await.map(|e| e.id), Some(0));
+        assert_invariants(&heap).await;
+        assert_eq!(heap.elevators_index.lock().await.get(&2), Some(&0));
+        assert_eq!(heap.elevators_index.lock().await.get(&5), Some(&2));
+
+        assert!(heap.remove_elevator(3).await.is_none());
+        assert_eq!(heap.len().await, 5);
+    }
+
+    #[tokio::test]
+    async fn inserting_a_car_again_moves_it_to_its_new_load() {
+        let mut heap = heap_of(&[1, 2, 3, 4]).await;
+
+        heap.insert_elevator(car(0, 9)).await.unwrap();
+        assert_invariants(&heap).await;
+        assert_eq!(heap.len().await, 4);
+        assert_eq!(heap.peek_elevator().await.map(|e| e.id), Some(1));
 
-        return (self.elevators.lock().await.len() - 1, None);
+        heap.insert_elevator(car(3, 0)).await.unwrap();
+        assert_invariants(&heap).await;
+        assert_eq!(heap.peek_elevator().await.map(|e| e.id), Some(3));
     }
 }
@@ -1,6 +1,5 @@
 use std::{collections::{HashMap, VecDeque}, sync::Arc};
-use crate::{elevator::ElevatorState, interfaces::ElevatorPool};
-use super::elevator_heap::MyError;
+use crate::{dispatch_error::DispatchError, elevator::ElevatorState, interfaces::ElevatorPool};
 use tokio::sync::Mutex;
 
 #[derive(Debug)]
@@ -26,29 +25,29 @@ impl ElevatorPool for ElevatorQueue  {
                 let mut elevator_index = self.elevators_index.lock().await;
                 elevator_index.remove(&e.clone().id);
 
-                return Some(e);
+                Some(e)
             },
             None => {
-                return None;
+                None
             }
         }
     }
 
-    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), MyError> {
+    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), DispatchError> {
         let mut elevator_index = self.elevators_index.lock().await;
 
         match elevator_index.get(&elevator.id) {
             Some(_) => {
-                return Ok(())
+                Ok(())
             },
             None => {
                 let mut elevators= self.elevators.lock().await;
                 elevator_index.insert(elevator.id, 0);
                 elevators.push_front(elevator);
-        
+
                 Ok(())
             }
-        }  
+        }
 
 
     }
@@ -58,27 +57,29 @@ impl ElevatorPool for ElevatorQueue  {
         let mut elevator_index = self.elevators_index.lock().await;
 
         match elevator_index.get(&elevator_id) {
-            Some(e) => {
-                let index = *e;
+            Some(_) => {
+                /* positions shift on every push_front, so look the elevator up in the queue itself */
+                let index = elevators.iter().position(|e| e.id == elevator_id)?;
 
                 elevator_index.remove(&elevator_id);
-                match elevators.remove(index){
-                    Some(e) => {
-                        return Some(e)
-                    },
-                    None => {
-                        return None;
-                    }
-                }
+                elevators.remove(index)
             },
             None => {
-                return None
+                None
             }
-        }  
+        }
 
     }
 
     async fn len(&self) -> usize {
-        return self.elevators.lock().await.len();
+        self.elevators.lock().await.len()
     }
-}
\ No newline at end of file
+
+    async fn list_elevators(&self) -> Vec<ElevatorState> {
+        self.elevators.lock().await.iter().cloned().collect()
+    }
+
+    async fn peek_elevator(&self) -> Option<ElevatorState> {
+        self.elevators.lock().await.back().cloned()
+    }
+}
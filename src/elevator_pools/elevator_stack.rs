@@ -0,0 +1,155 @@
+// Elevator is a stack, the elevator that became idle most recently is handed out first
+// Its door and motor are still warm, and it is probably near the last active floor
+
+// Removing or stacking an elevator again only clears its old slot, cleared slots are skipped once they reach the top
+use std::{collections::HashMap, sync::Arc};
+use crate::{dispatch_error::DispatchError, elevator::ElevatorState, interfaces::ElevatorPool};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+pub struct ElevatorStack {
+    elevators: Arc<Mutex<Vec<Option<ElevatorState>>>>,
+    pub elevators_index: Mutex<HashMap<usize, usize>>, // elevator id -> slot in the stack
+}
+
+impl ElevatorStack {
+    /* drop the cleared slots sitting on top of the stack */
+    fn trim(elevators: &mut Vec<Option<ElevatorState>>) {
+        while let Some(None) = elevators.last() {
+            elevators.pop();
+        }
+    }
+
+    /* squeeze out cleared slots once they outnumber the stacked elevators, keeps removal amortized O(1) */
+    fn compact(elevators: &mut Vec<Option<ElevatorState>>, elevators_index: &mut HashMap<usize, usize>) {
+        if elevators.len() <= 2 * elevators_index.len() {
+            return;
+        }
+
+        elevators.retain(|e| e.is_some());
+        for (slot, elevator) in elevators.iter().flatten().enumerate() {
+            elevators_index.insert(elevator.id, slot);
+        }
+    }
+}
+
+impl ElevatorPool for ElevatorStack {
+    fn new() -> Self {
+        ElevatorStack {
+            elevators: Arc::new(Mutex::new(Vec::new())),
+            elevators_index: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn get_elevator(&mut self) -> Option<ElevatorState> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        Self::trim(&mut elevators);
+        let elevator = elevators.pop().flatten()?;
+        elevators_index.remove(&elevator.id);
+        Self::trim(&mut elevators);
+
+        Some(elevator)
+    }
+
+    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), DispatchError> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        /* already stacked, it became idle again just now so it moves to the top */
+        if let Some(slot) = elevators_index.get(&elevator.id).copied() {
+            elevators[slot] = None;
+        }
+
+        elevators_index.insert(elevator.id, elevators.len());
+        elevators.push(Some(elevator));
+        Self::compact(&mut elevators, &mut elevators_index);
+
+        Ok(())
+    }
+
+    async fn remove_elevator(&mut self, elevator_id: usize) -> Option<ElevatorState> {
+        let mut elevators = self.elevators.lock().await;
+        let mut elevators_index = self.elevators_index.lock().await;
+
+        let slot = elevators_index.remove(&elevator_id)?;
+        let elevator = elevators[slot].take();
+        Self::trim(&mut elevators);
+        Self::compact(&mut elevators, &mut elevators_index);
+
+        elevator
+    }
+
+    async fn len(&self) -> usize {
+        self.elevators_index.lock().await.len()
+    }
+
+    async fn list_elevators(&self) -> Vec<ElevatorState> {
+        self.elevators.lock().await.iter().rev().flatten().cloned().collect()
+    }
+
+    async fn peek_elevator(&self) -> Option<ElevatorState> {
+        self.elevators.lock().await.iter().rev().flatten().next().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::Capacity;
+
+    fn car(id: usize, current_floor: usize) -> ElevatorState {
+        ElevatorState { current_floor, ..ElevatorState::new(id, Capacity::default()) }
+    }
+
+    async fn drain(stack: &mut ElevatorStack) -> Vec<usize> {
+        let mut ids = Vec::new();
+        while let Some(elevator) = stack.get_elevator().await {
+            ids.push(elevator.id);
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn the_latest_elevator_comes_out_first() {
+        let mut stack = ElevatorStack::new();
+        for id in 0..3 {
+            stack.insert_elevator(car(id, 0)).await.unwrap();
+        }
+
+        assert_eq!(drain(&mut stack).await, vec![2, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn stacking_an_elevator_again_moves_it_to_the_top() {
+        let mut stack = ElevatorStack::new();
+        for id in 0..3 {
+            stack.insert_elevator(car(id, 0)).await.unwrap();
+        }
+
+        stack.insert_elevator(car(0, 4)).await.unwrap();
+
+        assert_eq!(stack.len().await, 3);
+        assert_eq!(stack.peek_elevator().await.map(|e| (e.id, e.current_floor)), Some((0, 4)));
+        assert_eq!(stack.list_elevators().await.iter().map(|e| e.id).collect::<Vec<_>>(), vec![0, 2, 1]);
+        assert_eq!(drain(&mut stack).await, vec![0, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn cleared_slots_are_squeezed_out() {
+        let mut stack = ElevatorStack::new();
+        for id in 0..2 {
+            stack.insert_elevator(car(id, 0)).await.unwrap();
+        }
+
+        /* the bottom car keeps coming back to the top */
+        for floor in 0..10 {
+            stack.insert_elevator(car(0, floor)).await.unwrap();
+            stack.insert_elevator(car(1, floor)).await.unwrap();
+        }
+
+        assert!(stack.elevators.lock().await.len() <= 4);
+        assert_eq!(drain(&mut stack).await, vec![1, 0]);
+    }
+}
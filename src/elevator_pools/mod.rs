@@ -1,3 +1,101 @@
 pub mod elevator_heap;
 pub mod elevator_queue;
-pub mod elevator_stack;
\ No newline at end of file
+pub mod elevator_stack;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{dispatch_error::DispatchError, elevator::ElevatorState, interfaces::ElevatorPool};
+use elevator_heap::ElevatorHeap;
+use elevator_queue::ElevatorQueue;
+use elevator_stack::ElevatorStack;
+
+/* Which pool the central controller keeps its elevators in */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PoolKind {
+    #[default]
+    Queue, /* first in, first out */
+    Heap,  /* least loaded first */
+    Stack, /* most recently freed first */
+}
+
+impl PoolKind {
+    pub fn from_name(name: &str) -> Option<PoolKind> {
+        match name {
+            "queue" => Some(PoolKind::Queue),
+            "heap" => Some(PoolKind::Heap),
+            "stack" => Some(PoolKind::Stack),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> AnyElevatorPool {
+        match self {
+            PoolKind::Queue => AnyElevatorPool::Queue(ElevatorQueue::new()),
+            PoolKind::Heap => AnyElevatorPool::Heap(ElevatorHeap::new()),
+            PoolKind::Stack => AnyElevatorPool::Stack(ElevatorStack::new()),
+        }
+    }
+}
+
+/* A pool chosen at startup, forwards every call to the selected implementation */
+#[derive(Debug)]
+pub enum AnyElevatorPool {
+    Queue(ElevatorQueue),
+    Heap(ElevatorHeap),
+    Stack(ElevatorStack),
+}
+
+impl ElevatorPool for AnyElevatorPool {
+    fn new() -> Self {
+        PoolKind::default().build()
+    }
+
+    async fn get_elevator(&mut self) -> Option<ElevatorState> {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.get_elevator().await,
+            AnyElevatorPool::Heap(pool) => pool.get_elevator().await,
+            AnyElevatorPool::Stack(pool) => pool.get_elevator().await,
+        }
+    }
+
+    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), DispatchError> {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.insert_elevator(elevator).await,
+            AnyElevatorPool::Heap(pool) => pool.insert_elevator(elevator).await,
+            AnyElevatorPool::Stack(pool) => pool.insert_elevator(elevator).await,
+        }
+    }
+
+    async fn remove_elevator(&mut self, elevator_id: usize) -> Option<ElevatorState> {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.remove_elevator(elevator_id).await,
+            AnyElevatorPool::Heap(pool) => pool.remove_elevator(elevator_id).await,
+            AnyElevatorPool::Stack(pool) => pool.remove_elevator(elevator_id).await,
+        }
+    }
+
+    async fn len(&self) -> usize {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.len().await,
+            AnyElevatorPool::Heap(pool) => pool.len().await,
+            AnyElevatorPool::Stack(pool) => pool.len().await,
+        }
+    }
+
+    async fn list_elevators(&self) -> Vec<ElevatorState> {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.list_elevators().await,
+            AnyElevatorPool::Heap(pool) => pool.list_elevators().await,
+            AnyElevatorPool::Stack(pool) => pool.list_elevators().await,
+        }
+    }
+
+    async fn peek_elevator(&self) -> Option<ElevatorState> {
+        match self {
+            AnyElevatorPool::Queue(pool) => pool.peek_elevator().await,
+            AnyElevatorPool::Heap(pool) => pool.peek_elevator().await,
+            AnyElevatorPool::Stack(pool) => pool.peek_elevator().await,
+        }
+    }
+}
@@ -1,31 +1,30 @@
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration, usize};
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
 
-use actix_web::{web::{self, ServiceConfig}, HttpRequest, HttpResponse, Responder};
+use actix_web::{http::StatusCode, web::{self, ServiceConfig}, HttpRequest, HttpResponse, Responder};
 use actix_web_lab::sse::{self, Event};
 use futures::lock::Mutex;
-use tokio::sync::{broadcast::Receiver as BroadcastReceiver, mpsc::{Sender, Receiver, channel}};
+use tokio::sync::{broadcast::{Receiver as BroadcastReceiver, error::RecvError}, mpsc::{Sender, Receiver, channel}};
 use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::{central_elevator_controller::CentralElevatorController, elevator::ElevatorState, interfaces::CentralElevatorControllerI};
+use crate::{central_elevator_controller::{CentralElevatorController, HallCall}, dispatch_error::DispatchError, door::DoorCommand, elevator::{Direction, ElevatorState}, interfaces::CentralElevatorControllerI, parking::ParkingPolicy, schedule::{Schedule, ScheduledModes, TimeOfDay}};
 
 
 struct Visitor {
-    elevator: Option<usize>,
     floor: Option<usize>,
-
-    id: String, 
 }
 
 pub struct ElevatorHTTPHandlerImpl {
     central_elevator_controller: Arc<CentralElevatorController>,
     global_state_rx: Arc<BroadcastReceiver<ElevatorState>>,
     visitors: Mutex<HashMap<String, Mutex<Visitor>>>,
-    elevator_passenger: Mutex<HashMap<usize, Mutex<HashMap<String, bool>>>>
 }
 
+type EventSender = Sender<Result<Event, Infallible>>;
+type EventReceiver = Receiver<Result<Event, Infallible>>;
+
 pub trait ElevatorHTTPHandler {
-    async fn get_elevator(&self, from : usize, destination : usize) -> usize;
+    async fn get_elevator(&self, from : usize, destination : usize) -> Result<HallCall, DispatchError>;
     async fn listen_state(&self, tx : &Sender< Result<Event, Infallible>>);
     async fn print_elevator_state(&self) -> impl Responder;
 }
@@ -33,20 +32,200 @@ pub trait ElevatorHTTPHandler {
 pub fn register_job_routes(router_config: &mut ServiceConfig, elevator_controller:  Arc<CentralElevatorController>, global_state_rx: Arc<BroadcastReceiver<ElevatorState>>) {
     let job_http_handler = ElevatorHTTPHandlerImpl {
         central_elevator_controller: elevator_controller,
-        global_state_rx:global_state_rx,
+        global_state_rx,
         visitors: Mutex::new(HashMap::new()),
-        elevator_passenger: Mutex::new(HashMap::new())
     };
 
     router_config.app_data(web::Data::new(job_http_handler))
        .service(
             web::scope("/api/v1") 
             .route("/elevator/stream", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
-                let (tx, rx) : (Sender<Result<Event, Infallible>>, Receiver<Result<Event, Infallible>>) = channel(10);
+                let (tx, rx) : (EventSender, EventReceiver) = channel(10);
 
                 data.listen_state(&tx).await;
                 let data_stream: ReceiverStream<Result<Event, Infallible>> = ReceiverStream::new(rx);
-                return sse::Sse::from_stream(data_stream).with_keep_alive(Duration::from_secs(5))
+                sse::Sse::from_stream(data_stream).with_keep_alive(Duration::from_secs(5))
+            }))
+            .route("/building", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                let building = data.central_elevator_controller.building();
+                let floors: Vec<FloorLabel> = (0..building.floors).map(|floor| FloorLabel {
+                    number: building.lowest_floor + floor as i32,
+                    label: building.floor_label(floor),
+                }).collect();
+
+                let elevator_ids = data.central_elevator_controller.fleet_layout().await.elevators;
+                HTTPResponder::Ok(BuildingLayout { elevators: elevator_ids.len(), elevator_ids, floors })
+            }))
+            .route("/requests/{id}", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<String>| async move {
+                match data.central_elevator_controller.hall_call(&path.into_inner()).await {
+                    Ok(hall_call) => HTTPResponder::Ok(hall_call),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/requests/{id}", web::delete().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<String>| async move {
+                match data.central_elevator_controller.cancel_request(&path.into_inner()).await {
+                    Ok(hall_call) => HTTPResponder::Ok(hall_call),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/admin/elevators/{id}/out-of-service", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<usize>, query: web::Query<OutOfServiceQuery>| async move {
+                let controller = &data.central_elevator_controller;
+
+                /* without evacuate_to the car finishes its riders first */
+                let evacuate_to = match query.evacuate_to {
+                    Some(floor) => match controller.building().floor_index(floor) {
+                        Some(floor) => Some(floor),
+                        None => return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(floor)),
+                    },
+                    None => None,
+                };
+
+                match controller.take_out_of_service(path.into_inner(), evacuate_to).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/admin/elevators", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                match data.central_elevator_controller.add_car().await {
+                    Ok(elevator_id) => HTTPResponder::Ok(elevator_id),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/admin/elevators/{id}", web::delete().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<usize>| async move {
+                /* the car finishes its riders, parks and then leaves the fleet */
+                match data.central_elevator_controller.decommission_car(path.into_inner()).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/traffic", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                HTTPResponder::Ok(data.central_elevator_controller.traffic().await)
+            }))
+            .route("/admin/schedule", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                let (schedule, time_of_day, active) = data.central_elevator_controller.schedule().await;
+                HTTPResponder::Ok(ScheduleStatus { time_of_day, active, schedule })
+            }))
+            .route("/admin/schedule", web::put().to(|data: web::Data<ElevatorHTTPHandlerImpl>, body: web::Json<Schedule>| async move {
+                let controller = &data.central_elevator_controller;
+                if let Err(e) = controller.set_schedule(body.into_inner()).await {
+                    return HTTPResponder::BadRequest(e);
+                }
+
+                let (schedule, time_of_day, active) = controller.schedule().await;
+                HTTPResponder::Ok(ScheduleStatus { time_of_day, active, schedule })
+            }))
+            .route("/admin/parking", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                HTTPResponder::Ok(data.central_elevator_controller.parking_policy().await)
+            }))
+            .route("/admin/parking/{policy}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<String>| async move {
+                let name = path.into_inner();
+                let Some(policy) = ParkingPolicy::from_name(&name) else {
+                    return HTTPResponder::BadRequest(format!("unknown parking policy {}, use none, zones, lobby, entrance, upper or learned", name));
+                };
+
+                data.central_elevator_controller.set_parking_policy(policy).await;
+                HTTPResponder::Ok(policy)
+            }))
+            .route("/admin/elevators/health", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                HTTPResponder::Ok(data.central_elevator_controller.health().await)
+            }))
+            .route("/admin/elevators/{id}/in-service", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<usize>| async move {
+                match data.central_elevator_controller.return_to_service(path.into_inner()).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/hall-calls/{floor}/{direction}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<(i32, String)>| async move {
+                let (floor, direction) = path.into_inner();
+                let controller = &data.central_elevator_controller;
+
+                let direction = match direction.as_str() {
+                    "up" => Direction::Up,
+                    "down" => Direction::Down,
+                    _ => return HTTPResponder::BadRequest(format!("unknown direction {}, use up or down", direction)),
+                };
+
+                let Some(floor_index) = controller.building().floor_index(floor) else {
+                    return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(floor));
+                };
+
+                match controller.press_hall_button(floor_index, direction).await {
+                    Ok(hall_call) => HTTPResponder::Ok(hall_call),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/elevators/{id}/car-calls/{floor}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<(usize, i32)>| async move {
+                let (elevator_id, floor) = path.into_inner();
+                let controller = &data.central_elevator_controller;
+
+                let Some(floor_index) = controller.building().floor_index(floor) else {
+                    return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(floor));
+                };
+
+                match controller.press_car_call(elevator_id, floor_index).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/elevators/{id}/door/{button}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<(usize, String)>| async move {
+                let (elevator_id, button) = path.into_inner();
+                let Some(command) = DoorCommand::from_name(&button) else {
+                    return HTTPResponder::BadRequest(format!("unknown door button {}, use open, close or obstruct", button));
+                };
+
+                match data.central_elevator_controller.press_door_button(elevator_id, command).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/fire/recall", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, query: web::Query<FloorQuery>| async move {
+                let controller = &data.central_elevator_controller;
+
+                /* without a floor the configured recall floor is used */
+                let recall_floor = match query.floor {
+                    Some(floor) => match controller.building().floor_index(floor) {
+                        Some(floor) => Some(floor),
+                        None => return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(floor)),
+                    },
+                    None => None,
+                };
+
+                match controller.start_fire_recall(recall_floor).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/fire/recall", web::delete().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
+                match data.central_elevator_controller.end_fire_recall().await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/fire/elevators/{id}/go/{floor}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<(usize, i32)>| async move {
+                let (elevator_id, floor) = path.into_inner();
+                let controller = &data.central_elevator_controller;
+
+                let Some(floor_index) = controller.building().floor_index(floor) else {
+                    return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(floor));
+                };
+
+                match controller.firefighter_go_to(elevator_id, floor_index).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
+            }))
+            .route("/fire/elevators/{id}/door/{action}", web::post().to(|data: web::Data<ElevatorHTTPHandlerImpl>, path: web::Path<(usize, String)>| async move {
+                let (elevator_id, action) = path.into_inner();
+                let open = match action.as_str() {
+                    "open" => true,
+                    "close" => false,
+                    _ => return HTTPResponder::BadRequest(format!("unknown door action {}, use open or close", action)),
+                };
+
+                match data.central_elevator_controller.firefighter_door(elevator_id, open).await {
+                    Ok(()) => HTTPResponder::Ok(()),
+                    Err(e) => HTTPResponder::DispatchError(e),
+                }
             }))
             .route("/elevator/state", web::get().to(|data: web::Data<ElevatorHTTPHandlerImpl>| async move {
                 let _ = data.print_elevator_state().await;
@@ -57,33 +236,38 @@ pub fn register_job_routes(router_config: &mut ServiceConfig, elevator_controlle
                     let visitor_id = if let Some(cookie) = req.cookie("visitor_id") {
                         cookie.value().to_string()
                     } else {
-                        return HTTPResponder::Ok(())
+                        return HTTPResponder::BadRequest("visit / first to get a visitor_id cookie".to_string())
                     };
 
-                    let mut elevator_id: usize = 123;
+                    let mut hall_call: Result<HallCall, DispatchError> = Err(DispatchError::NoCarAvailable);
 
+                    let building = data.central_elevator_controller.building();
+                    let requested_floor = path.into_inner();
+                    let destination = match building.floor_index(requested_floor) {
+                        Some(floor) => floor,
+                        None => return HTTPResponder::DispatchError(DispatchError::FloorOutOfRange(requested_floor)),
+                    };
+
+                    let entrance_floor = building.entrance_floor();
                     let mut visitors = data.visitors.lock().await;
-                    let destination = path.into_inner() as usize;
                     match visitors.get(&visitor_id.clone()) {
                         Some(v) => {
-                            match v.lock().await.floor {
-                                Some(floor) => {
-                                    elevator_id = data.get_elevator(floor, destination).await;
-                                },
-                                None => {
-
-                                }
+                            if let Some(floor) = v.lock().await.floor {
+                                hall_call = data.get_elevator(floor, destination).await;
                             }
                         },
                         None => {
-                            let visitor = Visitor { elevator: None, floor: Some(0), id: visitor_id.clone() };
+                            let visitor = Visitor { floor: Some(entrance_floor) };
                             visitors.insert(visitor_id.clone(), Mutex::new(visitor));
-                            elevator_id = data.get_elevator(0, destination).await;
+                            hall_call = data.get_elevator(entrance_floor, destination).await;
                         }
                     }
 
-
-                    HTTPResponder::OkWithElevatorId(elevator_id)
+                    /* elevator_id is null while the call waits for a car, track it with the request id */
+                    match hall_call {
+                        Ok(hall_call) => HTTPResponder::Ok(hall_call),
+                        Err(e) => HTTPResponder::DispatchError(e),
+                    }
                 })),
         );
 }
@@ -93,19 +277,8 @@ impl ElevatorHTTPHandlerImpl {
 }
 
 impl ElevatorHTTPHandler for ElevatorHTTPHandlerImpl {
-    async fn get_elevator (&self,  from : usize, destination : usize) ->  usize{
-        let result = self.central_elevator_controller.call_for_an_elevator(from, destination).await;
-
-        match result {
-            Ok(id) => {
-                return id
-            },
-            Err(_) =>{
-
-            }
-        }
-
-        return 123
+    async fn get_elevator (&self,  from : usize, destination : usize) -> Result<HallCall, DispatchError> {
+        self.central_elevator_controller.call_for_an_elevator(from, destination).await
     }
     
     async fn listen_state(&self, tx : &Sender< Result<Event, Infallible>>) {
@@ -116,29 +289,32 @@ impl ElevatorHTTPHandler for ElevatorHTTPHandlerImpl {
 
         tokio::spawn(async move{
             loop {
-                match rx.recv().await {
-                    Ok(state) => {
-                        let json_val = serde_json::to_string(&state).map_or_else(|e| {
-                            return Err(e.to_string())
-                        }, Ok);
-    
-                        let data = Event::Data(
-                            sse::Data::new(json_val.unwrap()),
-                        );
-                        let event = Ok::<_, Infallible>(data);
-                        match tx_cloned.send(event).await {
-                            Ok(_) => {},
-                            Err(_) =>{}
-                        } ;
-                    },
-                    Err(e) => {
-    
+                if let Ok(state) = rx.recv().await {
+                    let json_val = serde_json::to_string(&state).map_or_else(|e| {
+                        Err(e.to_string())
+                    }, Ok);
+
+                    let data = Event::Data(
+                        sse::Data::new(json_val.unwrap()),
+                    );
+                    let event = Ok::<_, Infallible>(data);
+
+                    /* client went away */
+                    if tx_cloned.send(event).await.is_err() {
+                        break;
                     }
                 }
                 tokio::time::sleep(Duration::from_millis(100)).await;
             }
         });
-        
+
+        /* hall call status changes go out as named "request" events, onmessage only sees car states */
+        forward_events(self.central_elevator_controller.subscribe_requests(), None, "request", tx.clone());
+
+        /* the current fleet and traffic mode first, then a named event whenever one changes */
+        let controller = &self.central_elevator_controller;
+        forward_events(controller.subscribe_fleet(), Some(controller.fleet_layout().await), "fleet", tx.clone());
+        forward_events(controller.subscribe_traffic(), Some(controller.traffic().await), "traffic", tx.clone());
     }
     
     async fn print_elevator_state(&self) -> impl Responder {
@@ -152,6 +328,61 @@ impl ElevatorHTTPHandler for ElevatorHTTPHandlerImpl {
     }
 }
 
+/* a broadcast channel as named SSE events, `first` goes out before anything received */
+fn forward_events<T: Serialize + Clone + Send + 'static>(mut rx: BroadcastReceiver<T>, first: Option<T>, name: &'static str, tx: EventSender) {
+    tokio::spawn(async move {
+        let mut next = first;
+        loop {
+            let value = match next.take() {
+                Some(value) => value,
+                None => match rx.recv().await {
+                    Ok(value) => value,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            };
+
+            let Ok(json_val) = serde_json::to_string(&value) else {
+                continue;
+            };
+
+            let event = Ok::<_, Infallible>(Event::Data(sse::Data::new(json_val).event(name)));
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FloorQuery {
+    pub floor: Option<i32>, // building floor number
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OutOfServiceQuery {
+    pub evacuate_to: Option<i32>, // building floor number
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FloorLabel {
+    pub number: i32,
+    pub label: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BuildingLayout {
+    pub elevators: usize,
+    pub elevator_ids: Vec<usize>, // cars added at runtime get new ids, removed ids leave gaps
+    pub floors: Vec<FloorLabel>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduleStatus {
+    pub time_of_day: TimeOfDay,  // read from the elevator clock
+    pub active: ScheduledModes,  // what the rules covering it ask for
+    pub schedule: Schedule,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct CustomHTTPResponse<T: Serialize> {
@@ -161,14 +392,15 @@ pub struct CustomHTTPResponse<T: Serialize> {
 #[derive(Serialize, Deserialize)]
 pub struct CustomHTTPError {
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>, // set for dispatch errors, e.g. "no_car_available"
 }
 
-#[derive(Serialize, Deserialize)]
 pub enum HTTPResponder<T: Serialize> {
     Ok(T),
-    OkWithElevatorId(usize),
     BadRequest(String),
-    InternalServerError(String)
+    InternalServerError(String),
+    DispatchError(DispatchError),
 }
 
 impl<T: Serialize> Responder for HTTPResponder<T> {
@@ -177,9 +409,24 @@ impl<T: Serialize> Responder for HTTPResponder<T> {
     fn respond_to(self, _req: &actix_web::HttpRequest) -> HttpResponse<Self::Body> {
         match self {
             HTTPResponder::Ok(data) => HttpResponse::Ok().json(CustomHTTPResponse { data }),
-            HTTPResponder::BadRequest(msg) => HttpResponse::BadRequest().json(CustomHTTPError { error: msg }),
-            HTTPResponder::InternalServerError(msg) => HttpResponse::InternalServerError().json(CustomHTTPError {error: msg}),
-            HTTPResponder::OkWithElevatorId(data) =>  HttpResponse::Ok().json(CustomHTTPResponse { data }),
+            HTTPResponder::BadRequest(msg) => HttpResponse::BadRequest().json(CustomHTTPError { error: msg, code: None }),
+            HTTPResponder::InternalServerError(msg) => HttpResponse::InternalServerError().json(CustomHTTPError { error: msg, code: None }),
+            HTTPResponder::DispatchError(e) => {
+                let status = match e {
+                    DispatchError::FloorOutOfRange(_) | DispatchError::NoHallButton(_) => StatusCode::BAD_REQUEST,
+                    DispatchError::UnknownRequest(_) | DispatchError::UnknownCar(_) => StatusCode::NOT_FOUND,
+                    DispatchError::RequestFinished(_) | DispatchError::CarOutOfService(_) | DispatchError::LastCar(_) => StatusCode::CONFLICT,
+                    DispatchError::FireRecallActive => StatusCode::LOCKED,
+                    DispatchError::NoFireRecall => StatusCode::PRECONDITION_FAILED,
+                    DispatchError::PhaseTwoInUse(_) => StatusCode::FORBIDDEN,
+                    DispatchError::NoCarAvailable | DispatchError::CarFaulted(_) => StatusCode::SERVICE_UNAVAILABLE,
+                    DispatchError::CarUnreachable(_) => StatusCode::BAD_GATEWAY,
+                    DispatchError::ChannelClosed(_) => StatusCode::GONE,
+                    DispatchError::CarMissing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                HttpResponse::build(status).json(CustomHTTPError { error: e.to_string(), code: Some(e.code().to_string()) })
+            }
         }
     }
 }
\ No newline at end of file
@@ -1,25 +1,41 @@
-use std::fmt::Error;
+use std::{fmt::Debug, time::Duration};
 
-use crate::{elevator::ElevatorState, elevator_pools::elevator_heap::MyError};
+use futures::future::BoxFuture;
+
+use crate::{central_elevator_controller::{ElevatorRequest, HallCall}, dispatch_strategies::FleetSnapshot, dispatch_error::DispatchError, door::DoorState, elevator::ElevatorState};
 
 pub trait ElevatorPool {
     fn new() -> Self;
     async fn get_elevator(&mut self) -> Option<ElevatorState>;
-    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), MyError>;
+    async fn insert_elevator(&mut self, elevator: ElevatorState) -> Result<(), DispatchError>;
     async fn remove_elevator(&mut self, elevator_id: usize) -> Option<ElevatorState>;
     async fn len(&self) -> usize;
+    async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+    async fn list_elevators(&self) -> Vec<ElevatorState>;
+    async fn peek_elevator(&self) -> Option<ElevatorState>; /* the elevator get_elevator() would return */
+}
+
+pub trait DispatchStrategy: Debug + Send + Sync {
+    fn select_elevator(&self, fleet: &FleetSnapshot, request: &ElevatorRequest) -> Option<usize>;
 }
 
 pub trait ElevatorControllerI {
-    async fn go_to_floor(&self, destination: usize) -> Result<(), Error>;
+    async fn go_to_floor(&self, destination: usize) -> Result<(), DispatchError>;
 }
 
 pub trait CentralElevatorControllerI {
-    async fn call_for_an_elevator(&self, floor: usize, destination: usize) -> Result<usize, Error>;
+    async fn call_for_an_elevator(&self, floor: usize, destination: usize) -> Result<HallCall, DispatchError>;
     async fn print_states(&self);
 }
 
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Duration; /* time since the clock started */
+    fn time_of_day(&self) -> Option<Duration>; /* time since local midnight, None for a clock not tied to the wall, e.g. a simulation */
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
 pub trait ElevatorI {
-    async fn close_door(&mut self) -> Result<(), Error>;
-    async fn open_door(&mut self) -> Result<(), Error>;
+    fn set_door(&mut self, door: DoorState);
 }
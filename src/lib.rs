@@ -0,0 +1,28 @@
+// The traits are only implemented within this crate, so the futures of their async fns stay visible to tokio::spawn
+#![allow(async_fn_in_trait)]
+
+/* eprintln! unless logging::set_quiet(true) was called, declared before the modules so every one of them can use it */
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if !$crate::logging::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub mod building_config;
+pub mod central_elevator_controller;
+pub mod clock;
+pub mod dispatch_error;
+pub mod dispatch_strategies;
+pub mod door;
+pub mod elevator;
+pub mod elevator_controller;
+pub mod elevator_pools;
+pub mod http;
+pub mod interfaces;
+pub mod logging;
+pub mod parking;
+pub mod schedule;
+pub mod simulation;
+pub mod traffic;
@@ -1,27 +1,36 @@
 use std::{path::PathBuf, sync::Arc};
 
 use actix_files::NamedFile;
-use actix_web::{cookie::{Cookie, SameSite}, dev::{Service, ServiceRequest, ServiceResponse, Transform}, http::Error, web, App, HttpRequest, HttpResponse, HttpServer, Result};
-use central_elevator_controller::CentralElevatorController;
-use elevator::ElevatorState;
-use futures::future::Ready;
-use http::handler::register_job_routes;
+use actix_web::{cookie::{Cookie, SameSite}, web, App, HttpRequest, HttpResponse, HttpServer, Result};
+use elevator::building_config::BuildingConfig;
+use elevator::central_elevator_controller::CentralElevatorController;
+use elevator::clock::{AcceleratedClock, RealClock};
+use elevator::elevator::ElevatorState;
+use elevator::http::handler::register_job_routes;
+use elevator::interfaces::Clock;
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 use uuid::Uuid;
 
-mod elevator_pools;
-mod interfaces;
-mod elevator;
-mod central_elevator_controller;
-mod elevator_controller;
-mod http;
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
 
+    /* building model, e.g. `--config building.json --dispatch nearest-car` */
+    let args: Vec<String> = std::env::args().collect();
+    let building = BuildingConfig::from_args(&args).map_err(std::io::Error::other)?;
+    let dispatch_strategy = building.dispatch.build();
+
+    /* `--speedup 10` runs the elevators ten times faster than real time */
+    let clock: Arc<dyn Clock> = match args.iter().position(|a| a == "--speedup").and_then(|i| args.get(i + 1)) {
+        Some(factor) => {
+            let factor = factor.parse().map_err(|_| std::io::Error::other(format!("invalid --speedup {}", factor)))?;
+            Arc::new(AcceleratedClock::new(factor))
+        }
+        None => RealClock::shared(),
+    };
+
     /* elevators_state_stream */
     let (tx, rx) : (Sender<ElevatorState>, Receiver<ElevatorState>) = channel(10);
-    let elevator_controller = CentralElevatorController::new(tx.clone(), 3).await;
+    let elevator_controller = CentralElevatorController::new(tx.clone(), building, dispatch_strategy, clock).await;
 
     let rx_bind = Arc::new(rx);
     HttpServer::new(move || {
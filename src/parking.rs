@@ -0,0 +1,139 @@
+// Where idle cars wait for the next hall call
+// A car that goes idle is sent to a home floor no other idle car claimed, without opening its doors there
+// The move is cancelled as soon as the car gets a hall call or a car call
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{building_config::BuildingConfig, central_elevator_controller::CallRecord};
+
+/* Serialized as "none", "zones", "lobby", "entrance", "upper" and "learned" */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParkingPolicy {
+    #[default]
+    None,     /* cars stay where they went idle */
+    Zones,    /* one home per car, in the middle of equal zones of floors */
+    Lobby,    /* half the cars at the entrance floor, the others spread above it, for morning traffic */
+    Entrance, /* every car back to the entrance floor, for up-peak */
+    Upper,    /* spread over the upper half, each trip down starts from the top, for down-peak */
+    Learned,  /* the floors most recent hall calls came from, zones for the rest */
+}
+
+impl ParkingPolicy {
+    pub fn from_name(name: &str) -> Option<ParkingPolicy> {
+        match name {
+            "none" => Some(ParkingPolicy::None),
+            "zones" => Some(ParkingPolicy::Zones),
+            "lobby" => Some(ParkingPolicy::Lobby),
+            "entrance" => Some(ParkingPolicy::Entrance),
+            "upper" => Some(ParkingPolicy::Upper),
+            "learned" => Some(ParkingPolicy::Learned),
+            _ => None,
+        }
+    }
+
+    /* one home floor per car, the same floor may come up more than once */
+    pub fn home_floors(&self, building: &BuildingConfig, cars: usize, history: &VecDeque<CallRecord>) -> Vec<usize> {
+        match self {
+            ParkingPolicy::None => Vec::new(),
+            ParkingPolicy::Zones => zones(0, building.floors, cars),
+            ParkingPolicy::Lobby => {
+                let entrance = building.entrance_floor();
+                let at_lobby = cars.div_ceil(2);
+
+                let mut homes = vec![entrance; at_lobby];
+                homes.extend(zones(entrance + 1, building.floors, cars - at_lobby));
+                homes
+            }
+            ParkingPolicy::Entrance => vec![building.entrance_floor(); cars],
+            ParkingPolicy::Upper => zones(building.floors / 2, building.floors, cars),
+            ParkingPolicy::Learned => {
+                let mut calls = vec![0usize; building.floors];
+                for record in history {
+                    calls[record.from] += 1;
+                }
+
+                /* busiest floors first, the lower one on a tie */
+                let mut busiest: Vec<usize> = (0..building.floors).filter(|floor| calls[*floor] > 0).collect();
+                busiest.sort_by_key(|floor| (usize::MAX - calls[*floor], *floor));
+                busiest.truncate(cars);
+
+                let spread: Vec<usize> = zones(0, building.floors, cars).into_iter().filter(|floor| !busiest.contains(floor)).collect();
+                busiest.extend(spread.into_iter().take(cars - busiest.len()));
+                busiest
+            }
+        }
+    }
+}
+
+/* the middle floor of each of `count` equal zones from `lowest` up to the top floor */
+fn zones(lowest: usize, floors: usize, count: usize) -> Vec<usize> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    /* no floors above the entrance, everyone waits at the top */
+    if lowest >= floors {
+        return vec![floors - 1; count];
+    }
+
+    let span = floors - lowest;
+    (0..count).map(|zone| lowest + (zone * span + span / 2) / count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::elevator::Direction;
+
+    /* ten floors from the second basement, the entrance is floor index 2 */
+    fn building() -> BuildingConfig {
+        BuildingConfig { floors: 10, lowest_floor: -2, ..BuildingConfig::default() }
+    }
+
+    fn calls_from(floors: &[usize]) -> VecDeque<CallRecord> {
+        floors.iter().map(|from| CallRecord { at: Duration::ZERO, from: *from, to: None, direction: Direction::Up }).collect()
+    }
+
+    #[test]
+    fn zones_split_the_floors_evenly() {
+        assert_eq!(zones(0, 10, 2), vec![2, 7]);
+        assert_eq!(zones(0, 10, 3), vec![1, 5, 8]);
+        assert_eq!(zones(5, 10, 2), vec![6, 8]);
+        assert_eq!(zones(0, 10, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn zones_above_the_top_floor_wait_at_the_top() {
+        assert_eq!(zones(10, 10, 2), vec![9, 9]);
+    }
+
+    #[test]
+    fn every_policy_gives_one_home_per_car() {
+        let building = building();
+        let history = VecDeque::new();
+        let homes = |policy: ParkingPolicy, cars: usize| policy.home_floors(&building, cars, &history);
+
+        assert_eq!(homes(ParkingPolicy::None, 3), Vec::<usize>::new());
+        assert_eq!(homes(ParkingPolicy::Zones, 3), vec![1, 5, 8]);
+        assert_eq!(homes(ParkingPolicy::Entrance, 3), vec![2, 2, 2]);
+        /* two at the entrance, one in the middle of the floors above it */
+        assert_eq!(homes(ParkingPolicy::Lobby, 3), vec![2, 2, 6]);
+        assert_eq!(homes(ParkingPolicy::Upper, 2), vec![6, 8]);
+    }
+
+    #[test]
+    fn learned_homes_are_the_busiest_floors_then_zones() {
+        let building = building();
+
+        let history = calls_from(&[7, 2, 7, 7, 4, 2]);
+        assert_eq!(ParkingPolicy::Learned.home_floors(&building, 2, &history), vec![7, 2]);
+        assert_eq!(ParkingPolicy::Learned.home_floors(&building, 4, &history), vec![7, 2, 4, 1]);
+
+        /* nothing learned yet */
+        assert_eq!(ParkingPolicy::Learned.home_floors(&building, 3, &VecDeque::new()), vec![1, 5, 8]);
+    }
+}
@@ -0,0 +1,214 @@
+// Operating modes by time of day, e.g. up-peak in the morning and fewer cars at night
+// The time of day is local wall-clock time, a simulated clock starts at `clock_start` instead
+// Rules are applied in order, a later rule overrides what an earlier one sets
+use std::{cmp::Ordering, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{interfaces::Clock, parking::ParkingPolicy, traffic::TrafficMode};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/* Minutes since midnight, serialized as "HH:MM" */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    /* "07:30", "24:00" is the end of the day */
+    pub fn parse(text: &str) -> Option<TimeOfDay> {
+        let (hours, minutes) = text.split_once(':')?;
+        let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+
+        if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+            return None;
+        }
+
+        Some(TimeOfDay(hours * 60 + minutes))
+    }
+
+    pub fn minutes(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        TimeOfDay::parse(&text).ok_or(format!("invalid time of day {}, use HH:MM", text))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+/* One time window, fields left out are not changed by the rule */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,         // e.g. "lunch", only for operators
+    pub from: TimeOfDay,
+    pub to: TimeOfDay,                // exclusive, earlier than `from` runs past midnight, equal to `from` is all day
+    pub traffic: Option<TrafficMode>, // forced, the detected mode is ignored meanwhile
+    pub cars: Option<usize>,          // most cars in service, the others are taken out of service
+    pub parking: Option<ParkingPolicy>,
+}
+
+impl ScheduleRule {
+    pub fn covers(&self, time: TimeOfDay) -> bool {
+        match self.from.cmp(&self.to) {
+            Ordering::Less => self.from <= time && time < self.to,
+            Ordering::Greater => time >= self.from || time < self.to,
+            Ordering::Equal => true,
+        }
+    }
+}
+
+/* What the rules covering a time of day ask for, None keeps the building's own setting */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct ScheduledModes {
+    pub traffic: Option<TrafficMode>,
+    pub cars: Option<usize>,
+    pub parking: Option<ParkingPolicy>,
+}
+
+/* Loaded with the building config, replaced through the admin API */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub clock_start: TimeOfDay, // time of day when a simulated clock reads zero, real clocks follow the wall
+    pub rules: Vec<ScheduleRule>,
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.cars == Some(0)) {
+            return Err(format!("schedule rule {}-{} leaves no car in service", rule.from, rule.to));
+        }
+
+        Ok(())
+    }
+
+    pub fn time_of_day(&self, clock: &dyn Clock) -> TimeOfDay {
+        let minutes = match clock.time_of_day() {
+            Some(since_midnight) => since_midnight.as_secs() / 60,
+            None => self.clock_start.minutes() as u64 + clock.now().as_secs() / 60,
+        };
+
+        TimeOfDay((minutes % MINUTES_PER_DAY as u64) as u32)
+    }
+
+    pub fn active(&self, time: TimeOfDay) -> ScheduledModes {
+        let mut modes = ScheduledModes::default();
+
+        for rule in self.rules.iter().filter(|rule| rule.covers(time)) {
+            modes.traffic = rule.traffic.or(modes.traffic);
+            modes.cars = rule.cars.or(modes.cars);
+            modes.parking = rule.parking.or(modes.parking);
+        }
+
+        modes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    fn at(text: &str) -> TimeOfDay {
+        TimeOfDay::parse(text).unwrap()
+    }
+
+    fn rule(from: &str, to: &str) -> ScheduleRule {
+        ScheduleRule { name: None, from: at(from), to: at(to), traffic: None, cars: None, parking: None }
+    }
+
+    #[test]
+    fn parses_hours_and_minutes() {
+        assert_eq!(at("00:00").minutes(), 0);
+        assert_eq!(at("07:30").minutes(), 7 * 60 + 30);
+        assert_eq!(at("7:05").to_string(), "07:05");
+        assert_eq!(at("24:00").minutes(), 24 * 60);
+
+        for bad in ["24:01", "25:00", "12:60", "1230", "ab:cd", "-1:00", ""] {
+            assert_eq!(TimeOfDay::parse(bad), None, "{} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn rules_cover_their_window_up_to_the_end() {
+        let lunch = rule("12:00", "13:30");
+        assert!(lunch.covers(at("12:00")));
+        assert!(lunch.covers(at("13:29")));
+        assert!(!lunch.covers(at("13:30")));
+        assert!(!lunch.covers(at("11:59")));
+
+        let evening = rule("18:00", "24:00");
+        assert!(evening.covers(at("23:59")));
+        assert!(!evening.covers(at("00:00")));
+    }
+
+    #[test]
+    fn rules_run_past_midnight() {
+        let night = rule("22:00", "06:00");
+        assert!(night.covers(at("22:00")));
+        assert!(night.covers(at("23:59")));
+        assert!(night.covers(at("00:00")));
+        assert!(night.covers(at("05:59")));
+        assert!(!night.covers(at("06:00")));
+        assert!(!night.covers(at("12:00")));
+
+        assert!(rule("08:00", "08:00").covers(at("03:00")));
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let schedule = Schedule {
+            clock_start: TimeOfDay::default(),
+            rules: vec![
+                ScheduleRule { traffic: Some(TrafficMode::UpPeak), parking: Some(ParkingPolicy::Lobby), ..rule("07:00", "10:00") },
+                ScheduleRule { cars: Some(2), parking: Some(ParkingPolicy::Zones), ..rule("09:00", "17:00") },
+            ],
+        };
+
+        assert_eq!(schedule.active(at("06:00")), ScheduledModes::default());
+        assert_eq!(
+            schedule.active(at("08:00")),
+            ScheduledModes { traffic: Some(TrafficMode::UpPeak), cars: None, parking: Some(ParkingPolicy::Lobby) }
+        );
+        assert_eq!(
+            schedule.active(at("09:30")),
+            ScheduledModes { traffic: Some(TrafficMode::UpPeak), cars: Some(2), parking: Some(ParkingPolicy::Zones) }
+        );
+        assert_eq!(schedule.active(at("12:00")), ScheduledModes { traffic: None, cars: Some(2), parking: Some(ParkingPolicy::Zones) });
+    }
+
+    #[test]
+    fn a_simulated_day_starts_at_clock_start() {
+        let schedule = Schedule { clock_start: at("23:30"), rules: Vec::new() };
+        let clock = VirtualClock::new();
+        assert_eq!(schedule.time_of_day(&clock), at("23:30"));
+
+        clock.advance(Duration::from_secs(45 * 60));
+        assert_eq!(schedule.time_of_day(&clock), at("00:15"));
+    }
+
+    #[test]
+    fn a_rule_leaving_no_car_is_rejected() {
+        let schedule = Schedule { clock_start: TimeOfDay::default(), rules: vec![ScheduleRule { cars: Some(0), ..rule("22:00", "06:00") }] };
+        assert!(schedule.validate().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+pub mod report;
+pub mod traffic;
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::broadcast::{Receiver, Sender, channel, error::TryRecvError};
+
+use crate::{
+    building_config::BuildingConfig,
+    central_elevator_controller::{CentralElevatorController, HallCall},
+    clock::VirtualClock,
+    elevator::ElevatorState,
+    interfaces::{CentralElevatorControllerI, Clock, DispatchStrategy},
+};
+use report::{SimulationReport, Tracker};
+use traffic::TrafficConfig;
+
+/* how many times every task gets to run before the clock moves again */
+const SETTLE_ROUNDS: usize = 64;
+
+/* give up on passengers still travelling this long after the last arrival */
+const DRAIN_LIMIT: Duration = Duration::from_secs(3600);
+
+/* give up once no passenger boarded or arrived for this long while some are still travelling */
+const STALL_LIMIT: Duration = Duration::from_secs(600);
+
+/* Headless discrete-event run of the central controller */
+/* 1. Time is a VirtualClock, it jumps straight to the next passenger or the next elevator wake up */
+/* 2. Between jumps every task runs until it waits on the clock again */
+/* 3. Boarding and arrival are read from the request lifecycle, car usage from the state stream */
+pub struct Simulation {
+    building: BuildingConfig,
+    traffic: TrafficConfig,
+}
+
+impl Simulation {
+    pub fn new(building: BuildingConfig, traffic: TrafficConfig) -> Self {
+        Simulation { building, traffic }
+    }
+
+    /* must run on a current thread runtime, otherwise tasks keep running while the clock jumps */
+    pub async fn run(&self, dispatch_strategy: Box<dyn DispatchStrategy>) -> SimulationReport {
+        let clock = VirtualClock::new();
+        let shared_clock: Arc<dyn Clock> = Arc::new(clock.clone());
+
+        let (state_tx, mut state_rx): (Sender<ElevatorState>, Receiver<ElevatorState>) = channel(4096);
+        let controller = CentralElevatorController::new(state_tx, self.building.clone(), dispatch_strategy, shared_clock).await;
+
+        let mut request_rx = controller.subscribe_requests();
+
+        let passengers = self.traffic.generate(self.building.floors);
+        let last_arrival = passengers.last().map(|p| p.arrival).unwrap_or_default();
+        let mut tracker = Tracker::new(self.building.elevators);
+        let mut next_passenger = 0;
+
+        loop {
+            Self::settle().await;
+            Self::observe(&mut state_rx, &mut request_rx, &mut tracker, clock.now());
+
+            if next_passenger == passengers.len() && tracker.is_done() {
+                break;
+            }
+
+            if clock.now() > last_arrival + DRAIN_LIMIT {
+                eprintln!("simulation: giving up on passengers still travelling");
+                break;
+            }
+
+            if let Some(since) = tracker.stalled_since() && clock.now() - since > STALL_LIMIT {
+                eprintln!("simulation: nobody boarded or arrived for {}s, elevators stopped with passengers still travelling", STALL_LIMIT.as_secs());
+                break;
+            }
+
+            let next_arrival = passengers.get(next_passenger).map(|p| p.arrival);
+            let next_wakeup = clock.next_wakeup();
+            let passenger_first = match (next_arrival, next_wakeup) {
+                (Some(arrival), Some(wake_up)) => arrival <= wake_up,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+
+            match next_arrival {
+                Some(arrival) if passenger_first => {
+                    clock.advance(arrival.saturating_sub(clock.now()));
+
+                    let passenger = &passengers[next_passenger];
+                    next_passenger += 1;
+
+                    match controller.call_for_an_elevator(passenger.from, passenger.to).await {
+                        Ok(hall_call) => tracker.called(&hall_call, clock.now()),
+                        Err(e) => {
+                            eprintln!("simulation: passenger {} could not call: {}", passenger.id, e);
+                            tracker.failed();
+                        }
+                    }
+                }
+                _ => {
+                    /* the supervisor and the signal loops always sleep, a stuck car shows up as a stall instead */
+                    clock.advance_to_next_wakeup();
+                }
+            }
+        }
+
+        tracker.report(clock.now())
+    }
+
+    async fn settle() {
+        for _ in 0..SETTLE_ROUNDS {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn observe(state_rx: &mut Receiver<ElevatorState>, request_rx: &mut Receiver<HallCall>, tracker: &mut Tracker, now: Duration) {
+        loop {
+            match state_rx.try_recv() {
+                Ok(state) => tracker.observe(&state, now),
+                Err(TryRecvError::Lagged(missed)) => {
+                    eprintln!("simulation: missed {} elevator states", missed);
+                }
+                Err(_) => break,
+            }
+        }
+
+        loop {
+            match request_rx.try_recv() {
+                Ok(hall_call) => tracker.observe_request(&hall_call, now),
+                Err(TryRecvError::Lagged(missed)) => {
+                    eprintln!("simulation: missed {} request updates", missed);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch_strategies::DispatchKind;
+
+    /* `elevator-sim --dispatch <kind> --traffic up-peak.json`, everyone arrives at the lobby */
+    async fn up_peak(dispatch: DispatchKind) -> SimulationReport {
+        let traffic = TrafficConfig { duration_secs: 600, arrivals_per_minute: vec![6.0, 0.0, 0.0, 0.0, 0.0], seed: 7, ..TrafficConfig::default() };
+        Simulation::new(BuildingConfig::default(), traffic).run(dispatch.build()).await
+    }
+
+    #[tokio::test]
+    async fn destination_dispatch_shortens_up_peak_journeys() {
+        let destination = up_peak(DispatchKind::Destination).await;
+        let any_idle_car = up_peak(DispatchKind::PoolOrder).await;
+
+        assert_eq!(destination.served, destination.passengers);
+        assert!(destination.average_journey_secs < any_idle_car.average_journey_secs, "{} vs {}", destination, any_idle_car);
+        assert!(destination.max_wait_secs < any_idle_car.max_wait_secs, "{} vs {}", destination, any_idle_car);
+    }
+}
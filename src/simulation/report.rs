@@ -0,0 +1,228 @@
+// Collects what happened to every passenger from the request lifecycle, and how busy every car was from the state stream
+use std::{fmt, time::Duration};
+
+use serde::Serialize;
+
+use crate::{
+    central_elevator_controller::{HallCall, RequestStatus},
+    elevator::{Direction, ElevatorState},
+};
+
+#[derive(Debug, Clone, Default)]
+struct Journey {
+    request_id: String,
+    called_at: Duration,
+    queued: bool, // no car was free when called
+    boarded_at: Option<Duration>,
+    arrived_at: Option<Duration>,
+    cancelled: bool, // the call was withdrawn before the passenger arrived
+}
+
+impl Journey {
+    fn is_finished(&self) -> bool {
+        self.arrived_at.is_some() || self.cancelled
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CarUsage {
+    busy: Duration,
+    busy_since: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct Tracker {
+    journeys: Vec<Journey>,
+    cars: Vec<CarUsage>,
+    failed: usize, // calls the controller rejected, these passengers never got a request id
+    last_progress: Duration, // when a passenger last boarded, arrived or gave up
+}
+
+impl Tracker {
+    pub fn new(elevators: usize) -> Self {
+        Tracker {
+            journeys: Vec::new(),
+            cars: vec![CarUsage::default(); elevators],
+            failed: 0,
+            last_progress: Duration::ZERO,
+        }
+    }
+
+    pub fn called(&mut self, hall_call: &HallCall, now: Duration) {
+        self.journeys.push(Journey {
+            request_id: hall_call.request_id.clone(),
+            called_at: now,
+            queued: hall_call.status == RequestStatus::Queued,
+            ..Default::default()
+        });
+    }
+
+    pub fn failed(&mut self) {
+        self.failed += 1;
+    }
+
+    pub fn observe(&mut self, state: &ElevatorState, now: Duration) {
+        if let Some(car) = self.cars.get_mut(state.id) {
+            let busy = state.direction != Direction::Idle || state.is_door_open;
+            match (car.busy_since, busy) {
+                (None, true) => car.busy_since = Some(now),
+                (Some(since), false) => {
+                    car.busy += now - since;
+                    car.busy_since = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn observe_request(&mut self, hall_call: &HallCall, now: Duration) {
+        let Some(journey) = self.journeys.iter_mut().find(|j| j.request_id == hall_call.request_id) else {
+            return;
+        };
+
+        match hall_call.status {
+            RequestStatus::Boarded(_) => journey.boarded_at = journey.boarded_at.or(Some(now)),
+            RequestStatus::Arrived(_) => {
+                journey.boarded_at = journey.boarded_at.or(Some(now));
+                journey.arrived_at = Some(now);
+            }
+            RequestStatus::Cancelled => journey.cancelled = journey.arrived_at.is_none(),
+            _ => return,
+        }
+        self.last_progress = now;
+    }
+
+    /* since when nobody still travelling got any closer, None once everyone is done */
+    /* a passenger who called after the last progress has not been waiting for longer than that */
+    pub fn stalled_since(&self) -> Option<Duration> {
+        let oldest_call = self.journeys.iter().filter(|j| !j.is_finished()).map(|j| j.called_at).min()?;
+        Some(oldest_call.max(self.last_progress))
+    }
+
+    /* every passenger has arrived or given up, queued calls get a car eventually */
+    pub fn is_done(&self) -> bool {
+        self.journeys.iter().all(Journey::is_finished)
+    }
+
+    pub fn report(&self, now: Duration) -> SimulationReport {
+        let served: Vec<&Journey> = self.journeys.iter().filter(|j| j.arrived_at.is_some()).collect();
+        let waits: Vec<f64> = served
+            .iter()
+            .filter_map(|j| j.boarded_at.map(|b| (b - j.called_at).as_secs_f64()))
+            .collect();
+        let journeys: Vec<f64> = served
+            .iter()
+            .filter_map(|j| j.arrived_at.map(|a| (a - j.called_at).as_secs_f64()))
+            .collect();
+
+        let average = |values: &[f64]| {
+            if values.is_empty() {
+                0.0
+            } else {
+                values.iter().sum::<f64>() / values.len() as f64
+            }
+        };
+
+        let utilisation = self
+            .cars
+            .iter()
+            .map(|car| {
+                let busy = car.busy + car.busy_since.map(|since| now - since).unwrap_or_default();
+                if now.is_zero() {
+                    0.0
+                } else {
+                    busy.as_secs_f64() / now.as_secs_f64()
+                }
+            })
+            .collect();
+
+        SimulationReport {
+            passengers: self.journeys.len() + self.failed,
+            served: served.len(),
+            cancelled: self.journeys.iter().filter(|j| j.cancelled).count(),
+            failed: self.failed,
+            queued: self.journeys.iter().filter(|j| j.queued).count(),
+            average_wait_secs: average(&waits),
+            average_journey_secs: average(&journeys),
+            max_wait_secs: waits.iter().cloned().fold(0.0, f64::max),
+            simulated_secs: now.as_secs_f64(),
+            utilisation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub passengers: usize,
+    pub served: usize,
+    pub cancelled: usize, // the call was cancelled before the car arrived
+    pub failed: usize,    // the controller rejected the call
+    pub queued: usize, // no car was free when called, waited in the pending queue
+    pub average_wait_secs: f64,
+    pub average_journey_secs: f64,
+    pub max_wait_secs: f64,
+    pub simulated_secs: f64,
+    pub utilisation: Vec<f64>, // per car, share of the run spent moving or with doors open
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "passengers:      {} ({} served, {} queued)", self.passengers, self.served, self.queued)?;
+        if self.cancelled + self.failed > 0 {
+            writeln!(f, "not served:      {} cancelled, {} failed", self.cancelled, self.failed)?;
+        }
+        writeln!(f, "average wait:    {:.1}s", self.average_wait_secs)?;
+        writeln!(f, "average journey: {:.1}s", self.average_journey_secs)?;
+        writeln!(f, "max wait:        {:.1}s", self.max_wait_secs)?;
+        writeln!(f, "simulated:       {:.0}s", self.simulated_secs)?;
+        for (car, utilisation) in self.utilisation.iter().enumerate() {
+            writeln!(f, "car {} busy:      {:.1}%", car, utilisation * 100.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hall_call(request_id: &str, status: RequestStatus) -> HallCall {
+        HallCall { request_id: request_id.to_string(), from: 0, to: Some(3), direction: Direction::Up, car: None, eta_secs: None, status, updated_secs: 0.0 }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn a_stall_counts_from_the_last_boarding_or_arrival() {
+        let mut tracker = Tracker::new(1);
+        assert_eq!(tracker.stalled_since(), None);
+
+        tracker.called(&hall_call("a", RequestStatus::Assigned(0)), secs(10));
+        tracker.called(&hall_call("b", RequestStatus::Assigned(0)), secs(20));
+        assert_eq!(tracker.stalled_since(), Some(secs(10)));
+
+        /* a car on its way is no progress yet */
+        tracker.observe_request(&hall_call("a", RequestStatus::CarArriving(0)), secs(30));
+        assert_eq!(tracker.stalled_since(), Some(secs(10)));
+
+        tracker.observe_request(&hall_call("a", RequestStatus::Boarded(0)), secs(40));
+        assert_eq!(tracker.stalled_since(), Some(secs(40)));
+
+        tracker.observe_request(&hall_call("a", RequestStatus::Arrived(0)), secs(50));
+        tracker.observe_request(&hall_call("b", RequestStatus::Cancelled), secs(60));
+        assert_eq!(tracker.stalled_since(), None);
+    }
+
+    #[test]
+    fn a_new_caller_has_not_waited_through_the_stall() {
+        let mut tracker = Tracker::new(1);
+        tracker.called(&hall_call("a", RequestStatus::Assigned(0)), secs(10));
+        tracker.observe_request(&hall_call("a", RequestStatus::Arrived(0)), secs(30));
+
+        tracker.called(&hall_call("b", RequestStatus::Queued), secs(500));
+        assert_eq!(tracker.stalled_since(), Some(secs(500)));
+    }
+}
@@ -0,0 +1,144 @@
+// Passenger arrivals for the simulator
+// Every floor is a Poisson process, the destination is drawn from that floor's row of the origin/destination matrix
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/* Traffic to simulate, loaded from JSON (`--traffic traffic.json`) */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrafficConfig {
+    pub duration_secs: u64,             // passengers arrive during this window, the run ends once everyone arrived
+    pub arrivals_per_minute: Vec<f64>,  // per origin floor, lowest floor first, a single value applies to every floor
+    pub destination_weights: Vec<Vec<f64>>, // [origin][destination], empty means any other floor is equally likely
+    pub seed: u64,
+}
+
+impl Default for TrafficConfig {
+    fn default() -> Self {
+        TrafficConfig {
+            duration_secs: 3600,
+            arrivals_per_minute: vec![0.5],
+            destination_weights: Vec::new(),
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Passenger {
+    pub id: usize,
+    pub arrival: Duration,
+    pub from: usize,
+    pub to: usize,
+}
+
+/* xorshift64*, small and reproducible from a seed */
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: seed.max(1),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /* uniform in (0, 1] */
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /* time until the next arrival of a Poisson process */
+    pub fn exponential(&mut self, rate_per_sec: f64) -> f64 {
+        -self.next_f64().ln() / rate_per_sec
+    }
+
+    /* index drawn proportionally to its weight, None when every weight is zero */
+    pub fn weighted(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().filter(|w| **w > 0.0).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut pick = self.next_f64() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if *weight <= 0.0 {
+                continue;
+            }
+
+            if pick <= *weight {
+                return Some(i);
+            }
+            pick -= weight;
+        }
+
+        weights.iter().rposition(|w| *w > 0.0)
+    }
+}
+
+impl TrafficConfig {
+    fn rate_per_sec(&self, floor: usize) -> f64 {
+        let per_minute = match self.arrivals_per_minute.as_slice() {
+            [all] => *all,
+            rates => rates.get(floor).copied().unwrap_or(0.0),
+        };
+
+        per_minute / 60.0
+    }
+
+    fn destination_weights(&self, floors: usize, origin: usize) -> Vec<f64> {
+        let mut weights = match self.destination_weights.get(origin) {
+            Some(row) => (0..floors).map(|f| row.get(f).copied().unwrap_or(0.0)).collect(),
+            None => vec![1.0; floors],
+        };
+
+        /* nobody calls an elevator to stay where they are */
+        weights[origin] = 0.0;
+        weights
+    }
+
+    /* every passenger of the run, ordered by arrival */
+    pub fn generate(&self, floors: usize) -> Vec<Passenger> {
+        let mut rng = Rng::new(self.seed);
+        let window = self.duration_secs as f64;
+        let mut passengers: Vec<Passenger> = Vec::new();
+
+        for from in 0..floors {
+            let rate = self.rate_per_sec(from);
+            if rate <= 0.0 {
+                continue;
+            }
+
+            let weights = self.destination_weights(floors, from);
+            let mut at = rng.exponential(rate);
+            while at < window {
+                if let Some(to) = rng.weighted(&weights) {
+                    passengers.push(Passenger {
+                        id: 0,
+                        arrival: Duration::from_secs_f64(at),
+                        from,
+                        to,
+                    });
+                }
+                at += rng.exponential(rate);
+            }
+        }
+
+        passengers.sort_by_key(|p| p.arrival);
+        for (id, passenger) in passengers.iter_mut().enumerate() {
+            passenger.id = id;
+        }
+
+        passengers
+    }
+}
@@ -0,0 +1,244 @@
+// Traffic pattern of the building, read from the hall calls of the last few minutes
+// Trips starting at the entrance floor are people arriving (up-peak), trips ending there are people leaving (down-peak)
+// A hall button call has no destination, going down from above the entrance it is counted as leaving
+// The mode narrows the cars a hall call may get before the dispatch strategy picks one of them
+use std::{collections::VecDeque, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    central_elevator_controller::{CallRecord, ElevatorRequest},
+    dispatch_strategies::FleetSnapshot,
+    elevator::{Direction, ElevatorState},
+    parking::ParkingPolicy,
+};
+
+pub const TRAFFIC_WINDOW: Duration = Duration::from_secs(5 * 60);
+const MIN_CALLS: usize = 6;       /* fewer calls in the window is light traffic */
+const PEAK_SHARE: f64 = 0.6;      /* of the calls, starting or ending at the entrance */
+const TWO_WAY_SHARE: f64 = 0.3;   /* both starting and ending at the entrance, e.g. lunch */
+
+/* Serialized as "light", "up_peak", "down_peak", "two_way" and "inter_floor" */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficMode {
+    #[default]
+    Light,
+    UpPeak,     /* most calls start at the entrance */
+    DownPeak,   /* most calls end at the entrance */
+    TwoWay,     /* heavy both to and from the entrance */
+    InterFloor, /* mostly between upper floors */
+}
+
+impl TrafficMode {
+    /* peaks decide where idle cars wait, otherwise the configured policy does */
+    pub fn parking(&self, configured: ParkingPolicy) -> ParkingPolicy {
+        match self {
+            TrafficMode::UpPeak => ParkingPolicy::Entrance,
+            TrafficMode::DownPeak => ParkingPolicy::Upper,
+            TrafficMode::Light | TrafficMode::TwoWay | TrafficMode::InterFloor => configured,
+        }
+    }
+
+    /* idle cars at the entrance kept for calls from the entrance, half the fleet in up-peak, one car in two-way traffic */
+    fn lobby_reserve(&self, cars: usize) -> usize {
+        match self {
+            TrafficMode::UpPeak => cars.div_ceil(2),
+            TrafficMode::TwoWay if cars > 1 => 1,
+            _ => 0,
+        }
+    }
+
+    /* leave out the cars this mode keeps from the call, the strategy picks from the rest */
+    /* up-peak and two-way keep idle cars at the entrance for arrivals, down-peak sweeps down calls top-down */
+    /* light and inter-floor traffic leave every car to the strategy, so does a mode that would leave none */
+    pub fn eligible(&self, fleet: &FleetSnapshot, request: &ElevatorRequest, entrance: usize) -> FleetSnapshot {
+        let is_idle = |e: &ElevatorState| e.direction == Direction::Idle && !e.is_moving;
+
+        let reserved: Vec<usize> = if request.from == entrance {
+            Vec::new()
+        } else {
+            fleet
+                .elevators
+                .iter()
+                .filter(|e| is_idle(e) && e.current_floor == entrance)
+                .map(|e| e.id)
+                .take(self.lobby_reserve(fleet.elevators.len()))
+                .collect()
+        };
+
+        /* a car below the caller or on its way up reaches a down call only after turning around */
+        let sweeps_down = |e: &ElevatorState| {
+            *self != TrafficMode::DownPeak
+                || request.direction != Direction::Down
+                || (e.current_floor >= request.from && e.direction != Direction::Up)
+        };
+
+        let mut eligible = fleet.clone();
+        eligible.retain(|e| !reserved.contains(&e.id) && sweeps_down(e));
+        if eligible.elevators.is_empty() {
+            return fleet.clone();
+        }
+
+        eligible
+    }
+}
+
+/* What the detection saw, serialized for the API and the SSE stream */
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TrafficReport {
+    pub mode: TrafficMode,
+    pub calls: usize,         // hall calls in the window
+    pub from_entrance: f64,   // share of them starting at the entrance floor
+    pub to_entrance: f64,     // share of them ending at the entrance floor
+    pub since_secs: f64,      // clock time the mode was detected
+}
+
+pub fn classify(history: &VecDeque<CallRecord>, entrance: usize, now: Duration) -> TrafficReport {
+    let recent: Vec<&CallRecord> = history.iter().filter(|record| now.saturating_sub(record.at) <= TRAFFIC_WINDOW).collect();
+    let calls = recent.len();
+
+    let from = recent.iter().filter(|record| record.from == entrance).count();
+    let to = recent
+        .iter()
+        .filter(|record| match record.to {
+            Some(to) => to == entrance,
+            None => record.direction == Direction::Down && record.from > entrance,
+        })
+        .count();
+
+    let share = |count: usize| if calls == 0 { 0.0 } else { count as f64 / calls as f64 };
+    let (from_entrance, to_entrance) = (share(from), share(to));
+
+    let mode = if calls < MIN_CALLS {
+        TrafficMode::Light
+    } else if from_entrance >= PEAK_SHARE {
+        TrafficMode::UpPeak
+    } else if to_entrance >= PEAK_SHARE {
+        TrafficMode::DownPeak
+    } else if from_entrance >= TWO_WAY_SHARE && to_entrance >= TWO_WAY_SHARE {
+        TrafficMode::TwoWay
+    } else {
+        TrafficMode::InterFloor
+    };
+
+    TrafficReport { mode, calls, from_entrance, to_entrance, since_secs: now.as_secs_f64() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::elevator::Capacity;
+
+    const ENTRANCE: usize = 0;
+
+    fn call(at_secs: u64, from: usize, to: Option<usize>) -> CallRecord {
+        let direction = match to {
+            Some(to) if to < from => Direction::Down,
+            None if from > ENTRANCE => Direction::Down,
+            _ => Direction::Up,
+        };
+        CallRecord { at: Duration::from_secs(at_secs), from, to, direction }
+    }
+
+    fn history(trips: &[(usize, Option<usize>)]) -> VecDeque<CallRecord> {
+        trips.iter().enumerate().map(|(i, (from, to))| call(i as u64 * 10, *from, *to)).collect()
+    }
+
+    fn mode(trips: &[(usize, Option<usize>)]) -> TrafficMode {
+        classify(&history(trips), ENTRANCE, Duration::from_secs(trips.len() as u64 * 10)).mode
+    }
+
+    #[test]
+    fn few_calls_are_light_traffic() {
+        assert_eq!(mode(&[(0, Some(5)), (0, Some(6)), (0, Some(7))]), TrafficMode::Light);
+    }
+
+    #[test]
+    fn arrivals_at_the_entrance_are_up_peak() {
+        let trips = [(0, Some(3)), (0, Some(5)), (0, Some(8)), (0, Some(2)), (0, Some(9)), (4, Some(6)), (0, Some(4))];
+        assert_eq!(mode(&trips), TrafficMode::UpPeak);
+    }
+
+    #[test]
+    fn leaving_through_the_entrance_is_down_peak() {
+        /* hall buttons going down count as leaving */
+        let trips = [(7, Some(0)), (5, None), (9, Some(0)), (3, None), (6, Some(0)), (2, Some(4)), (8, Some(0))];
+        assert_eq!(mode(&trips), TrafficMode::DownPeak);
+    }
+
+    #[test]
+    fn both_ways_through_the_entrance_is_two_way() {
+        let trips = [(0, Some(3)), (0, Some(5)), (0, Some(8)), (7, Some(0)), (5, Some(0)), (9, Some(0)), (2, Some(4)), (6, Some(3))];
+        assert_eq!(mode(&trips), TrafficMode::TwoWay);
+    }
+
+    #[test]
+    fn trips_between_upper_floors_are_inter_floor() {
+        let trips = [(2, Some(4)), (6, Some(3)), (8, Some(9)), (5, Some(1)), (3, Some(7)), (0, Some(6)), (9, Some(0))];
+        assert_eq!(mode(&trips), TrafficMode::InterFloor);
+    }
+
+    #[test]
+    fn calls_older_than_the_window_are_forgotten() {
+        let mut history = history(&[(0, Some(3)), (0, Some(5)), (0, Some(8)), (0, Some(2)), (0, Some(9)), (0, Some(4))]);
+        let report = classify(&history, ENTRANCE, TRAFFIC_WINDOW + Duration::from_secs(60));
+        assert_eq!(report.mode, TrafficMode::Light);
+        assert_eq!(report.calls, 0);
+
+        history.push_back(call(TRAFFIC_WINDOW.as_secs() + 60, 5, Some(0)));
+        assert_eq!(classify(&history, ENTRANCE, TRAFFIC_WINDOW + Duration::from_secs(60)).calls, 1);
+    }
+
+    fn car(id: usize, current_floor: usize, direction: Direction) -> ElevatorState {
+        ElevatorState { current_floor, direction, is_moving: direction != Direction::Idle, ..ElevatorState::new(id, Capacity::default()) }
+    }
+
+    fn fleet(elevators: Vec<ElevatorState>) -> FleetSnapshot {
+        FleetSnapshot { next_idle: elevators.first().map(|e| e.id), next_moving_up: None, next_moving_down: None, plans: HashMap::new(), elevators }
+    }
+
+    fn request(from: usize, direction: Direction) -> ElevatorRequest {
+        ElevatorRequest { id: "test".to_string(), from, to: None, direction }
+    }
+
+    fn ids(fleet: &FleetSnapshot) -> Vec<usize> {
+        fleet.elevators.iter().map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn up_peak_keeps_lobby_cars_for_arrivals() {
+        let cars = fleet(vec![car(0, 0, Direction::Idle), car(1, 0, Direction::Idle), car(2, 4, Direction::Idle), car(3, 7, Direction::Up)]);
+
+        let upstairs = TrafficMode::UpPeak.eligible(&cars, &request(5, Direction::Down), ENTRANCE);
+        assert_eq!(ids(&upstairs), vec![2, 3]);
+        assert_eq!(upstairs.next_idle, None);
+
+        let lobby = TrafficMode::UpPeak.eligible(&cars, &request(ENTRANCE, Direction::Up), ENTRANCE);
+        assert_eq!(ids(&lobby), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn two_way_keeps_one_lobby_car() {
+        let cars = fleet(vec![car(0, 0, Direction::Idle), car(1, 0, Direction::Idle), car(2, 4, Direction::Idle)]);
+        assert_eq!(ids(&TrafficMode::TwoWay.eligible(&cars, &request(5, Direction::Down), ENTRANCE)), vec![1, 2]);
+    }
+
+    #[test]
+    fn down_peak_sends_cars_from_above() {
+        let cars = fleet(vec![car(0, 2, Direction::Idle), car(1, 8, Direction::Down), car(2, 9, Direction::Up), car(3, 6, Direction::Idle)]);
+
+        assert_eq!(ids(&TrafficMode::DownPeak.eligible(&cars, &request(5, Direction::Down), ENTRANCE)), vec![1, 3]);
+        assert_eq!(ids(&TrafficMode::DownPeak.eligible(&cars, &request(5, Direction::Up), ENTRANCE)), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn a_mode_never_leaves_a_call_without_cars() {
+        let cars = fleet(vec![car(0, 0, Direction::Idle), car(1, 1, Direction::Up)]);
+
+        assert_eq!(ids(&TrafficMode::DownPeak.eligible(&cars, &request(5, Direction::Down), ENTRANCE)), vec![0, 1]);
+        assert_eq!(ids(&TrafficMode::InterFloor.eligible(&cars, &request(5, Direction::Down), ENTRANCE)), vec![0, 1]);
+    }
+}